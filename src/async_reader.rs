@@ -0,0 +1,153 @@
+// Lets the sync parsers read from async readers, so the format is only
+// described once. The parser runs over the bytes fetched so far, if it needs
+// more they are fetched and it runs again from the start. Headers are small, so
+// this only takes a few rounds. Everything fetched is kept until the parser is
+// done, so at most `MAX_FETCHED` bytes are fetched.
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+use crate::{error::Error, Result};
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+const MAX_FETCHED: u64 = 64 * 1024 * 1024;
+
+/// The bytes fetched from an async reader by offset, read by the sync parser.
+pub(crate) struct Fetched {
+    chunks: BTreeMap<u64, Vec<u8>>,
+    /// The length of the input, if known.
+    len: Option<u64>,
+    position: u64,
+    /// The first offset the parser needed that wasn't fetched yet.
+    missing: Option<u64>,
+}
+
+impl Fetched {
+    fn new(len: Option<u64>) -> Self {
+        Self {
+            chunks: BTreeMap::new(),
+            len,
+            position: 0,
+            missing: None,
+        }
+    }
+
+    /// Runs `parse` from the start, returns the offset it stopped at if the
+    /// data there wasn't fetched yet.
+    fn run<T>(
+        &mut self,
+        parse: &mut impl FnMut(&mut Fetched) -> Result<T>,
+    ) -> std::result::Result<Result<T>, u64> {
+        self.position = 0;
+        self.missing = None;
+        let res = parse(self);
+        match self.missing {
+            // the parser may have turned the error into anything, so it is
+            // not looked at
+            Some(missing) => Err(missing),
+            None => Ok(res),
+        }
+    }
+}
+
+impl Read for Fetched {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some((&start, chunk)) = self.chunks.range(..=self.position).next_back() {
+            let offset = (self.position - start) as usize;
+            if offset < chunk.len() {
+                let n = buf.len().min(chunk.len() - offset);
+                buf[..n].copy_from_slice(&chunk[offset..offset + n]);
+                self.position += n as u64;
+                return Ok(n);
+            }
+        }
+
+        if buf.is_empty() || self.len.is_some_and(|len| self.position >= len) {
+            return Ok(0);
+        }
+        self.missing.get_or_insert(self.position);
+        Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "data not fetched yet",
+        ))
+    }
+}
+
+impl Seek for Fetched {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.and_then(|len| len.checked_add_signed(offset)),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position =
+            position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek"))?;
+        Ok(self.position)
+    }
+}
+
+/// Runs `parse` over `reader`, which is read front to back and may be read
+/// past the end of what `parse` needs.
+pub(crate) async fn parse_stream<T>(
+    reader: &mut (impl AsyncRead + Unpin),
+    mut parse: impl FnMut(&mut Fetched) -> Result<T>,
+) -> Result<T> {
+    let mut fetched = Fetched::new(None);
+    loop {
+        let missing = match fetched.run(&mut parse) {
+            Ok(res) => return res,
+            Err(missing) => missing,
+        };
+
+        if missing >= MAX_FETCHED {
+            return Err(Error::TooLarge(MAX_FETCHED));
+        }
+
+        // everything is kept in one chunk, doubling it every round keeps the
+        // parsing time linear
+        let mut chunk = fetched.chunks.remove(&0).unwrap_or_default();
+        let target = (missing + 1)
+            .max(chunk.len() as u64 * 2)
+            .clamp(CHUNK_SIZE, MAX_FETCHED);
+        let wanted = target - chunk.len() as u64;
+        let read = (&mut *reader).take(wanted).read_to_end(&mut chunk).await?;
+        if (read as u64) < wanted {
+            fetched.len = Some(chunk.len() as u64);
+        }
+        fetched.chunks.insert(0, chunk);
+    }
+}
+
+/// Runs `parse` over `reader`, fetching only the parts it reads.
+pub(crate) async fn parse_seekable<T>(
+    reader: &mut (impl AsyncRead + AsyncSeek + Unpin),
+    mut parse: impl FnMut(&mut Fetched) -> Result<T>,
+) -> Result<T> {
+    let len = reader.seek(SeekFrom::End(0)).await?;
+    let mut fetched = Fetched::new(Some(len));
+    let mut total = 0;
+    loop {
+        let missing = match fetched.run(&mut parse) {
+            Ok(res) => return res,
+            Err(missing) => missing,
+        };
+        if total >= MAX_FETCHED {
+            return Err(Error::TooLarge(MAX_FETCHED));
+        }
+
+        reader.seek(SeekFrom::Start(missing)).await?;
+        let mut chunk = Vec::new();
+        total += (&mut *reader)
+            .take(CHUNK_SIZE)
+            .read_to_end(&mut chunk)
+            .await? as u64;
+        if chunk.is_empty() {
+            // the input got shorter since we looked
+            fetched.len = Some(missing);
+        }
+        fetched.chunks.insert(missing, chunk);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{fmt, io, string::FromUtf8Error};
+
+use zip::result::ZipError;
+
+use crate::{preview::PreviewFormat, saves::FactorioVersion};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The input ended before the structure was fully read.
+    UnexpectedEof,
+    /// A byte that should be an enum discriminant has no known variant.
+    InvalidEnum {
+        field: &'static str,
+        value: u8,
+        offset: u64,
+    },
+    /// A string field does not contain valid UTF-8.
+    InvalidUtf8 {
+        field: &'static str,
+        source: FromUtf8Error,
+    },
+    /// The data uses a part of the format we cannot read yet.
+    Unsupported {
+        field: &'static str,
+        offset: u64,
+    },
+    /// The save was written by a Factorio version we cannot read.
+    UnsupportedVersion(FactorioVersion),
+    /// A field to be written is `None` although the Factorio version it is
+    /// written for stores it.
+    MissingField {
+        field: &'static str,
+        version: FactorioVersion,
+    },
+    /// A value can't be stored in the format it is written in, like Lua
+    /// functions since 1.0.
+    UnsupportedValue(&'static str),
+    /// The archive does not contain a `level.dat` or `level.dat0` entry.
+    MissingLevelDat,
+    /// The archive does not contain a `level-init.dat` entry.
+    MissingLevelInitDat,
+    /// `level.datmetadata` announces more `level.datN` chunks than the archive
+    /// contains, holds the name of the first missing one.
+    MissingLevelDataChunk(String),
+    /// A `level.datN` chunk decompressed to a different size than announced by
+    /// `level.datmetadata`.
+    TruncatedLevelData {
+        entry: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// The preview image of a save is not a valid image of its format.
+    InvalidPreview(PreviewFormat),
+    /// A Lua table reference in `script.dat`, or one to be written, doesn't
+    /// refer to a table stored before it.
+    InvalidTableRef(u32),
+    /// A property tree or Lua table is nested deeper than we are willing to
+    /// follow, holds what was being read or written.
+    TooDeep(&'static str),
+    /// Reading the header from an async reader needed more than the given
+    /// number of bytes, which only happens with corrupt lengths.
+    TooLarge(u64),
+    /// A zip archive read without [`zip::ZipArchive`] is malformed.
+    InvalidZip(&'static str),
+    /// A zip archive read without [`zip::ZipArchive`] uses a feature we
+    /// cannot read, like encryption.
+    UnsupportedZip(&'static str),
+    Zip(ZipError),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnexpectedEof => write!(f, "unexpected end of input"),
+            Error::InvalidEnum {
+                field,
+                value,
+                offset,
+            } => write!(f, "invalid value {value} for `{field}` at offset {offset}"),
+            Error::InvalidUtf8 { field, source } => {
+                write!(f, "invalid UTF-8 in `{field}`: {source}")
+            }
+            Error::Unsupported { field, offset } => {
+                write!(f, "unsupported data in `{field}` at offset {offset}")
+            }
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported factorio version {version:?}")
+            }
+            Error::MissingField { field, version } => {
+                write!(f, "`{field}` is required by factorio {version:?}")
+            }
+            Error::UnsupportedValue(field) => write!(f, "`{field}` can't be written"),
+            Error::MissingLevelDat => write!(f, "no level.dat found in save"),
+            Error::MissingLevelInitDat => write!(f, "no level-init.dat found in save"),
+            Error::MissingLevelDataChunk(name) => write!(f, "missing level data chunk {name}"),
+            Error::TruncatedLevelData {
+                entry,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{entry} has {actual} bytes of level data, expected {expected}"
+            ),
+            Error::InvalidPreview(format) => write!(f, "invalid {format:?} preview image"),
+            Error::InvalidTableRef(id) => write!(f, "lua table reference to unknown table {id}"),
+            Error::TooDeep(field) => write!(f, "`{field}` is nested too deeply"),
+            Error::TooLarge(limit) => {
+                write!(f, "more than {limit} bytes needed to read the header")
+            }
+            Error::InvalidZip(reason) => write!(f, "invalid zip archive: {reason}"),
+            Error::UnsupportedZip(feature) => write!(f, "unsupported zip archive: {feature}"),
+            Error::Zip(e) => write!(f, "zip error: {e}"),
+            Error::Io(e) => write!(f, "io error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUtf8 { source, .. } => Some(source),
+            Error::Zip(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        if value.kind() == io::ErrorKind::UnexpectedEof {
+            return Error::UnexpectedEof;
+        }
+        // readers like `LevelDataReader` have to pass our errors through `io::Error`
+        if value.get_ref().is_some_and(|e| e.is::<Error>()) {
+            return *value.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+        Error::Io(value)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(value: ZipError) -> Self {
+        match value {
+            ZipError::Io(e) => e.into(),
+            e => Error::Zip(e),
+        }
+    }
+}
@@ -0,0 +1,352 @@
+// Since 1.1.14 the world is no longer stored as a single `level.dat` but split
+// into `level.dat0`, `level.dat1`, ... which are zlib compressed on their own,
+// plus a `level.datmetadata` entry. Every chunk but the last one decompresses
+// to exactly `LevelDataMetadata::CHUNK_SIZE` bytes.
+use std::{
+    io,
+    io::{Cursor, Read},
+};
+
+use flate2::read::ZlibDecoder;
+
+use crate::{error::Error, reader::FactorioReader, source::SaveSource, Result};
+
+/// The contents of `level.datmetadata`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct LevelDataMetadata {
+    /// Size of the whole level data after decompressing all chunks.
+    pub uncompressed_size: u64,
+}
+
+impl LevelDataMetadata {
+    pub const CHUNK_SIZE: u64 = 1024 * 1024;
+
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        Ok(Self {
+            uncompressed_size: u64::read(&[0, 0, 0, 0].into(), reader)?,
+        })
+    }
+
+    /// The number of `level.datN` chunks the level data is split into.
+    pub fn chunk_count(&self) -> u64 {
+        self.uncompressed_size.div_ceil(Self::CHUNK_SIZE)
+    }
+
+    /// The decompressed size of chunk `index`.
+    pub fn chunk_size(&self, index: u64) -> u64 {
+        self.uncompressed_size
+            .saturating_sub(index * Self::CHUNK_SIZE)
+            .min(Self::CHUNK_SIZE)
+    }
+}
+
+/// Which of the two copies of the level data in a save is read.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum LevelDataFile {
+    /// `level.dat` or the `level.datN` chunks, the game as it was saved.
+    #[default]
+    Level,
+    /// `level-init.dat`, the scenario as it was when the game was started. It
+    /// is always stored as a single uncompressed entry.
+    LevelInit,
+}
+
+/// Where the level data lives inside a save archive.
+#[derive(PartialEq, Debug, Clone)]
+pub enum LevelDataLayout {
+    /// A single uncompressed `level.dat`, used before 1.1.14.
+    Single(String),
+    /// zlib compressed `level.datN` chunks, in order.
+    Chunked {
+        chunks: Vec<String>,
+        metadata: Option<LevelDataMetadata>,
+    },
+}
+
+impl LevelDataLayout {
+    /// Looks for the level data entries in `source`.
+    ///
+    /// Fails with [`Error::MissingLevelDat`] if there is neither a `level.dat`
+    /// nor a `level.dat0` and with [`Error::MissingLevelDataChunk`] if
+    /// `level.datmetadata` lists more chunks than the archive contains.
+    pub fn find(source: &mut impl SaveSource) -> Result<Self> {
+        let mut single = None;
+        let mut first_chunk = None;
+        for name in source.file_names() {
+            // the entries are inside the save's root folder, e.g. `test_1_1/level.dat`
+            let file_name = name.rsplit('/').next().unwrap_or(name);
+            if file_name == "level.dat" {
+                single = Some(name.to_string());
+            } else if file_name == "level.dat0" {
+                first_chunk = Some(name.to_string());
+            }
+        }
+
+        if let Some(first_chunk) = first_chunk {
+            let prefix = first_chunk[..first_chunk.len() - 1].to_string();
+            // the entries are not necessarily stored in order, so look them up by name
+            let chunks: Vec<_> = (0..)
+                .map(|i| format!("{prefix}{i}"))
+                .take_while(|name| source.contains(name))
+                .collect();
+
+            let metadata = match source.open(&format!("{prefix}metadata"))? {
+                Some(mut file) => Some(LevelDataMetadata::read(&mut file)?),
+                None => None,
+            };
+            if let Some(metadata) = metadata {
+                if (chunks.len() as u64) < metadata.chunk_count() {
+                    return Err(Error::MissingLevelDataChunk(format!(
+                        "{prefix}{}",
+                        chunks.len()
+                    )));
+                }
+            }
+
+            return Ok(LevelDataLayout::Chunked { chunks, metadata });
+        }
+
+        single
+            .map(LevelDataLayout::Single)
+            .ok_or(Error::MissingLevelDat)
+    }
+}
+
+/// Exposes the level data of a save as one continuous stream, regardless of
+/// whether it is stored as a single `level.dat` or as `level.datN` chunks.
+///
+/// A single `level.dat` is read straight from the source. Chunks are buffered
+/// compressed while they are being read, so at most one chunk is kept in
+/// memory at a time. If the save has a `level.datmetadata`, each chunk is
+/// checked to decompress to the size it announces.
+///
+/// # Examples
+///
+/// ```
+/// use factorio::{level_data::LevelDataReader, saves::get_save_header};
+///
+/// let file = std::fs::File::open("test/test_2_0_13_ext.zip").unwrap();
+/// let mut archive = zip::ZipArchive::new(file).unwrap();
+/// let mut level_data = LevelDataReader::new(&mut archive).unwrap();
+/// let header = get_save_header(&mut level_data).unwrap();
+/// ```
+pub struct LevelDataReader<'a, S> {
+    /// Taken by a single `level.dat`, which is read from the source directly.
+    source: Option<&'a mut S>,
+    entries: Vec<String>,
+    compressed: bool,
+    metadata: Option<LevelDataMetadata>,
+    next_entry: usize,
+    current: Option<Box<dyn Read + 'a>>,
+    current_read: u64,
+}
+
+impl<'a, S: SaveSource> LevelDataReader<'a, S> {
+    pub fn new(source: &'a mut S) -> Result<Self> {
+        let layout = LevelDataLayout::find(source)?;
+        Ok(Self::with_layout(source, layout))
+    }
+
+    pub fn with_layout(source: &'a mut S, layout: LevelDataLayout) -> Self {
+        let (entries, compressed, metadata) = match layout {
+            LevelDataLayout::Single(name) => (vec![name], false, None),
+            LevelDataLayout::Chunked { chunks, metadata } => (chunks, true, metadata),
+        };
+
+        Self {
+            source: Some(source),
+            entries,
+            compressed,
+            metadata,
+            next_entry: 0,
+            current: None,
+            current_read: 0,
+        }
+    }
+
+    fn check_current(&self) -> Result<()> {
+        let Some(metadata) = &self.metadata else {
+            return Ok(());
+        };
+
+        let index = self.next_entry - 1;
+        let expected = metadata.chunk_size(index as u64);
+        if self.current_read != expected {
+            return Err(Error::TruncatedLevelData {
+                entry: self.entries[index].clone(),
+                expected,
+                actual: self.current_read,
+            });
+        }
+        Ok(())
+    }
+
+    fn open_next(&mut self) -> Result<bool> {
+        let Some(name) = self.entries.get(self.next_entry) else {
+            return Ok(false);
+        };
+        self.next_entry += 1;
+        self.current_read = 0;
+
+        if !self.compressed {
+            // the only entry, so it can keep the source borrowed
+            let source = self
+                .source
+                .take()
+                .expect("a single level.dat is only opened once");
+            let file = source.open(name)?.ok_or(Error::MissingLevelDat)?;
+            self.current = Some(Box::new(file));
+            return Ok(true);
+        }
+
+        let source = self
+            .source
+            .as_deref_mut()
+            .expect("chunks never take the source");
+        let Some(mut file) = source.open(name)? else {
+            return Err(Error::MissingLevelDataChunk(name.clone()));
+        };
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+
+        self.current = Some(Box::new(ZlibDecoder::new(Cursor::new(buf))));
+        Ok(true)
+    }
+}
+
+impl<S: SaveSource> Read for LevelDataReader<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let into_io = |e| match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        };
+
+        loop {
+            if let Some(current) = &mut self.current {
+                let read = current.read(buf)?;
+                if read != 0 {
+                    self.current_read += read as u64;
+                    return Ok(read);
+                }
+                self.current = None;
+                self.check_current().map_err(into_io)?;
+            }
+
+            if !self.open_next().map_err(into_io)? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::test_util::write_zip;
+
+    fn read_level_data(path: &str) -> Vec<u8> {
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut buf = Vec::new();
+        LevelDataReader::new(&mut archive)
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        buf
+    }
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut archive = ZipArchive::new(File::open("test/test_1_1_14.zip").unwrap()).unwrap();
+        let metadata =
+            LevelDataMetadata::read(&mut archive.by_name("test1_1_14/level.datmetadata").unwrap())
+                .unwrap();
+        assert_eq!(metadata.uncompressed_size, 0x11edf0);
+        assert_eq!(metadata.chunk_count(), 2);
+        assert_eq!(metadata.chunk_size(0), LevelDataMetadata::CHUNK_SIZE);
+        assert_eq!(metadata.chunk_size(1), 0x1edf0);
+    }
+
+    #[test]
+    fn test_missing_chunk() {
+        let size = (LevelDataMetadata::CHUNK_SIZE + 1).to_le_bytes();
+        let chunk = compress(&vec![0; LevelDataMetadata::CHUNK_SIZE as usize]);
+        let mut archive = ZipArchive::new(write_zip(&[
+            ("save/level.dat0", &chunk),
+            ("save/level.datmetadata", &size),
+        ]))
+        .unwrap();
+
+        let err = LevelDataLayout::find(&mut archive).unwrap_err();
+        assert!(matches!(err, Error::MissingLevelDataChunk(name) if name == "save/level.dat1"));
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let size = (LevelDataMetadata::CHUNK_SIZE + 1).to_le_bytes();
+        let chunk = compress(&[0; 1000]);
+        let mut archive = ZipArchive::new(write_zip(&[
+            ("save/level.dat0", &chunk),
+            ("save/level.dat1", &compress(&[0])),
+            ("save/level.datmetadata", &size),
+        ]))
+        .unwrap();
+
+        let err = LevelDataReader::new(&mut archive)
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<Error>().unwrap();
+        assert!(matches!(
+            *err,
+            Error::TruncatedLevelData {
+                ref entry,
+                expected: LevelDataMetadata::CHUNK_SIZE,
+                actual: 1000,
+            } if entry == "save/level.dat0"
+        ));
+    }
+
+    #[test]
+    fn test_single() {
+        let mut archive = ZipArchive::new(File::open("test/test_1_1.zip").unwrap()).unwrap();
+        assert_eq!(
+            LevelDataLayout::find(&mut archive).unwrap(),
+            LevelDataLayout::Single("test_1_1/level.dat".to_string())
+        );
+        assert_eq!(read_level_data("test/test_1_1.zip").len(), 1175594);
+    }
+
+    #[test]
+    fn test_chunked() {
+        // the chunks are stored out of order in this archive
+        let mut archive = ZipArchive::new(File::open("test/test_2_0_13_ext.zip").unwrap()).unwrap();
+        assert_eq!(
+            LevelDataLayout::find(&mut archive).unwrap(),
+            LevelDataLayout::Chunked {
+                chunks: vec![
+                    "test_2_0_13_ext/level.dat0".to_string(),
+                    "test_2_0_13_ext/level.dat1".to_string(),
+                ],
+                metadata: Some(LevelDataMetadata {
+                    uncompressed_size: 0x15b2aa,
+                }),
+            }
+        );
+        assert_eq!(read_level_data("test/test_2_0_13_ext.zip").len(), 0x15b2aa);
+        assert_eq!(read_level_data("test/test_1_1_14.zip").len(), 0x11edf0);
+    }
+}
@@ -1,6 +1,24 @@
+#[cfg(feature = "async")]
+mod async_reader;
+mod error;
+pub mod level_data;
+mod lua_source;
+pub mod map_settings;
+pub mod preview;
+pub mod property_tree;
+pub mod prototype_ids;
 mod reader;
+pub mod save_file;
 //pub mod saves;
 pub mod saves;
+pub mod script_data;
+pub mod source;
+#[cfg(test)]
+mod test_util;
+mod writer;
+mod zip_stream;
+
+pub use error::{Error, Result};
 
 // Mostly based on https://forums.factorio.com/viewtopic.php?f=5&t=8568&p=277892&hilit=level.dat+python#p277892
 // Function that writes the save in factorio: `Scenario::saveMap()`
@@ -0,0 +1,585 @@
+// Up to 0.18 every table in `script.dat` is stored as Lua source code that
+// rebuilds it, written by serpent (https://github.com/pkulchenko/serpent):
+//
+//     do local _={a={},b=nil,[0x1p+0]="x"};local __={};_.b=_.a;return _;end
+//
+// The table is a single constructor. A table that is referenced more than
+// once is only written the first time, every other place holds `nil` and is
+// assigned by a statement after the constructor. Functions are written as
+// their bytecode, `((loadstring or load)("<bytecode>",'@serialized'))`.
+use std::collections::BTreeMap;
+
+use crate::{
+    error::Error,
+    script_data::{LuaValue, MAX_LUA_DEPTH},
+    Result,
+};
+
+/// Parses the source of a table written by serpent.
+///
+/// Fails with [`Error::Unsupported`] for source serpent doesn't write, or
+/// which uses tables or functions as keys.
+pub(crate) fn parse(source: &[u8]) -> Result<LuaValue> {
+    let mut parser = Parser {
+        source,
+        position: 0,
+        next_table_id: 0,
+    };
+    parser.expect(b"do")?;
+    parser.expect(b"local")?;
+    parser.expect(b"_")?;
+    parser.expect(b"=")?;
+    let mut root = parser.value(0)?;
+    if !matches!(root, LuaValue::Table { .. }) {
+        return Err(parser.unsupported());
+    }
+    parser.expect(b";")?;
+
+    let mut paths = BTreeMap::new();
+    table_paths(&root, &mut Vec::new(), &mut paths);
+    if parser.eat(b"local") {
+        // the tables and functions used as keys, which we don't support
+        parser.expect(b"__")?;
+        parser.expect(b"=")?;
+        parser.expect(b"{")?;
+        parser.expect(b"}")?;
+        parser.expect(b";")?;
+    }
+    while !parser.eat(b"return") {
+        let offset = parser.position;
+        let target = parser.path()?;
+        parser.expect(b"=")?;
+        let value = if parser.peek() == Some(b'_') {
+            let source = parser.path()?;
+            resolve(&root, &paths, &source)
+                .and_then(|path| get(&root, &path))
+                .map(|value| match value {
+                    LuaValue::Table { id, .. } => LuaValue::TableRef(*id),
+                    value => value.clone(),
+                })
+        } else {
+            Some(parser.value(0)?)
+        };
+        parser.expect(b";")?;
+
+        let assigned = value.and_then(|value| {
+            let mut target = resolve(&root, &paths, &target)?;
+            let key = target.pop()?;
+            let LuaValue::Table { entries, .. } = get_mut(&mut root, &target)? else {
+                return None;
+            };
+            match entries.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, v)) => *v = value,
+                None => entries.push((key, value)),
+            }
+            Some(())
+        });
+        if assigned.is_none() {
+            return Err(Error::Unsupported {
+                field: "lua source",
+                offset: offset as u64,
+            });
+        }
+    }
+    parser.expect(b"_")?;
+    parser.expect(b";")?;
+    parser.expect(b"end")?;
+    parser.skip_whitespace();
+    if parser.position != source.len() {
+        return Err(parser.unsupported());
+    }
+    Ok(root)
+}
+
+/// Collects the keys leading from the root to every table.
+fn table_paths(
+    value: &LuaValue,
+    path: &mut Vec<LuaValue>,
+    paths: &mut BTreeMap<u32, Vec<LuaValue>>,
+) {
+    if let LuaValue::Table { id, entries } = value {
+        paths.insert(*id, path.clone());
+        for (key, value) in entries {
+            path.push(key.clone());
+            table_paths(value, path, paths);
+            path.pop();
+        }
+    }
+}
+
+/// Turns a path which may lead through table references into the keys
+/// leading to the same place without any, `None` if it leads nowhere.
+fn resolve(
+    root: &LuaValue,
+    paths: &BTreeMap<u32, Vec<LuaValue>>,
+    path: &[LuaValue],
+) -> Option<Vec<LuaValue>> {
+    let mut res = Vec::with_capacity(path.len());
+    for key in path {
+        match get(root, &res)? {
+            LuaValue::Table { .. } => {}
+            LuaValue::TableRef(id) => res = paths.get(id)?.clone(),
+            _ => return None,
+        }
+        res.push(key.clone());
+    }
+    Some(res)
+}
+
+fn get<'a>(mut value: &'a LuaValue, path: &[LuaValue]) -> Option<&'a LuaValue> {
+    for key in path {
+        let LuaValue::Table { entries, .. } = value else {
+            return None;
+        };
+        value = entries.iter().find_map(|(k, v)| (k == key).then_some(v))?;
+    }
+    Some(value)
+}
+
+fn get_mut<'a>(mut value: &'a mut LuaValue, path: &[LuaValue]) -> Option<&'a mut LuaValue> {
+    for key in path {
+        let LuaValue::Table { entries, .. } = value else {
+            return None;
+        };
+        value = entries
+            .iter_mut()
+            .find_map(|(k, v)| (k == key).then_some(v))?;
+    }
+    Some(value)
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    position: usize,
+    /// The ID of the next table, tables are numbered in the order they start.
+    next_table_id: u32,
+}
+
+impl Parser<'_> {
+    fn unsupported(&self) -> Error {
+        Error::Unsupported {
+            field: "lua source",
+            offset: self.position as u64,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .source
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.source.get(self.position).copied()
+    }
+
+    /// Consumes `token` if it comes next. Names only match whole, so `_`
+    /// doesn't match the start of `__`.
+    fn eat(&mut self, token: &[u8]) -> bool {
+        self.skip_whitespace();
+        if !self.source[self.position..].starts_with(token) {
+            return false;
+        }
+        let end = self.position + token.len();
+        if is_name_byte(token[token.len() - 1])
+            && self.source.get(end).is_some_and(|&b| is_name_byte(b))
+        {
+            return false;
+        }
+        self.position = end;
+        true
+    }
+
+    fn expect(&mut self, token: &[u8]) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unsupported())
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        let start = self.position;
+        while self
+            .source
+            .get(self.position)
+            .is_some_and(|&b| is_name_byte(b))
+        {
+            self.position += 1;
+        }
+        if start == self.position || self.source[start].is_ascii_digit() {
+            return Err(self.unsupported());
+        }
+        // only ASCII bytes were consumed
+        Ok(String::from_utf8_lossy(&self.source[start..self.position]).into_owned())
+    }
+
+    /// A place in the table, `_` followed by `.name` and `[key]` parts.
+    fn path(&mut self) -> Result<Vec<LuaValue>> {
+        self.expect(b"_")?;
+        let mut res = Vec::new();
+        loop {
+            if self.eat(b".") {
+                res.push(LuaValue::String(self.name()?));
+            } else if self.eat(b"[") {
+                // tables and functions as keys are referred to through `__`
+                if self.peek() == Some(b'_') {
+                    return Err(self.unsupported());
+                }
+                res.push(self.value(0)?);
+                self.expect(b"]")?;
+            } else {
+                return Ok(res);
+            }
+        }
+    }
+
+    /// `depth` is the number of tables the value is in.
+    fn value(&mut self, depth: usize) -> Result<LuaValue> {
+        if depth > MAX_LUA_DEPTH {
+            return Err(Error::TooDeep("lua value"));
+        }
+
+        match self.peek() {
+            Some(b'{') => self.table(depth),
+            Some(b'"' | b'\'') => String::from_utf8(self.string()?)
+                .map(LuaValue::String)
+                .map_err(|source| Error::InvalidUtf8 {
+                    field: "lua string",
+                    source,
+                }),
+            Some(b'(') => self.function(),
+            _ if self.eat(b"nil") => Ok(LuaValue::Nil),
+            _ if self.eat(b"true") => Ok(LuaValue::Bool(true)),
+            _ if self.eat(b"false") => Ok(LuaValue::Bool(false)),
+            _ => {
+                let value = self.number()?;
+                if self.eat(b"/") {
+                    // serpent writes NaN as `0/0`
+                    return Ok(LuaValue::Number(value / self.number()?));
+                }
+                Ok(LuaValue::Number(value))
+            }
+        }
+    }
+
+    fn table(&mut self, depth: usize) -> Result<LuaValue> {
+        self.expect(b"{")?;
+        let id = self.next_table_id;
+        self.next_table_id += 1;
+
+        let mut entries = Vec::new();
+        let mut index = 1;
+        while !self.eat(b"}") {
+            let key = if self.eat(b"[") {
+                let key = self.value(depth + 1)?;
+                self.expect(b"]")?;
+                self.expect(b"=")?;
+                Some(key)
+            } else if self
+                .peek()
+                .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+            {
+                let start = self.position;
+                let name = self.name()?;
+                if self.eat(b"=") {
+                    Some(LuaValue::String(name))
+                } else {
+                    // a value like `true` rather than a key
+                    self.position = start;
+                    None
+                }
+            } else {
+                None
+            };
+            let key = key.unwrap_or_else(|| {
+                index += 1;
+                LuaValue::Number((index - 1) as f64)
+            });
+
+            // nil values are placeholders for references assigned later
+            let value = self.value(depth + 1)?;
+            if value != LuaValue::Nil {
+                entries.push((key, value));
+            }
+
+            if !self.eat(b",") && !self.eat(b";") {
+                self.expect(b"}")?;
+                break;
+            }
+        }
+        Ok(LuaValue::Table { id, entries })
+    }
+
+    fn function(&mut self) -> Result<LuaValue> {
+        self.expect(b"(")?;
+        self.expect(b"(")?;
+        self.expect(b"loadstring")?;
+        self.expect(b"or")?;
+        self.expect(b"load")?;
+        self.expect(b")")?;
+        self.expect(b"(")?;
+        let bytecode = self.string()?;
+        // the chunk name, always `'@serialized'`
+        if self.eat(b",") {
+            self.string()?;
+        }
+        self.expect(b")")?;
+        self.expect(b")")?;
+        Ok(LuaValue::Function(bytecode))
+    }
+
+    /// A quoted string with Lua's escapes, the contents aren't necessarily
+    /// UTF-8.
+    fn string(&mut self) -> Result<Vec<u8>> {
+        let quote = match self.peek() {
+            Some(quote @ (b'"' | b'\'')) => quote,
+            _ => return Err(self.unsupported()),
+        };
+        self.position += 1;
+
+        let mut res = Vec::new();
+        loop {
+            let Some(&byte) = self.source.get(self.position) else {
+                return Err(self.unsupported());
+            };
+            self.position += 1;
+            if byte == quote {
+                return Ok(res);
+            }
+            if byte != b'\\' {
+                res.push(byte);
+                continue;
+            }
+
+            let Some(&escaped) = self.source.get(self.position) else {
+                return Err(self.unsupported());
+            };
+            self.position += 1;
+            match escaped {
+                b'a' => res.push(0x07),
+                b'b' => res.push(0x08),
+                b'f' => res.push(0x0c),
+                b'n' | b'\n' => res.push(b'\n'),
+                b'r' => res.push(b'\r'),
+                b't' => res.push(b'\t'),
+                b'v' => res.push(0x0b),
+                b'\\' | b'"' | b'\'' => res.push(escaped),
+                b'0'..=b'9' => {
+                    // up to three decimal digits
+                    let mut value = (escaped - b'0') as u32;
+                    for _ in 0..2 {
+                        match self.source.get(self.position) {
+                            Some(&digit @ b'0'..=b'9') => {
+                                value = value * 10 + (digit - b'0') as u32;
+                                self.position += 1;
+                            }
+                            _ => break,
+                        }
+                    }
+                    let Ok(value) = u8::try_from(value) else {
+                        return Err(self.unsupported());
+                    };
+                    res.push(value);
+                }
+                _ => return Err(self.unsupported()),
+            }
+        }
+    }
+
+    /// A decimal or, as written by Factorio since 0.14, hexadecimal number.
+    fn number(&mut self) -> Result<f64> {
+        self.skip_whitespace();
+        let negative = self.eat(b"-");
+        if self.eat(b"math.huge") {
+            return Ok(if negative {
+                f64::NEG_INFINITY
+            } else {
+                f64::INFINITY
+            });
+        }
+
+        let rest = &self.source[self.position..];
+        let value = if rest.starts_with(b"0x") || rest.starts_with(b"0X") {
+            self.position += 2;
+            self.hex_number()?
+        } else {
+            let start = self.position;
+            while self.source.get(self.position).is_some_and(|&b| {
+                b.is_ascii_digit()
+                    || b == b'.'
+                    || b == b'e'
+                    || b == b'E'
+                    || ((b == b'+' || b == b'-')
+                        && matches!(self.source[self.position - 1], b'e' | b'E'))
+            }) {
+                self.position += 1;
+            }
+            std::str::from_utf8(&self.source[start..self.position])
+                .ok()
+                .and_then(|number| number.parse().ok())
+                .ok_or_else(|| self.unsupported())?
+        };
+        Ok(if negative { -value } else { value })
+    }
+
+    /// The part of a hexadecimal number after `0x`, like `1.8p+4`.
+    fn hex_number(&mut self) -> Result<f64> {
+        let mut mantissa = 0.0;
+        let mut exponent = 0;
+        let mut digits = 0;
+        let mut fraction = false;
+        loop {
+            match self.source.get(self.position) {
+                Some(&b'.') if !fraction => fraction = true,
+                Some(&digit) if digit.is_ascii_hexdigit() => {
+                    mantissa = mantissa * 16.0 + (digit as char).to_digit(16).unwrap() as f64;
+                    if fraction {
+                        exponent -= 4;
+                    }
+                    digits += 1;
+                }
+                _ => break,
+            }
+            self.position += 1;
+        }
+        if digits == 0 {
+            return Err(self.unsupported());
+        }
+
+        if matches!(self.source.get(self.position), Some(b'p' | b'P')) {
+            self.position += 1;
+            let start = self.position;
+            if matches!(self.source.get(self.position), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            while self
+                .source
+                .get(self.position)
+                .is_some_and(u8::is_ascii_digit)
+            {
+                self.position += 1;
+            }
+            let power: i32 = std::str::from_utf8(&self.source[start..self.position])
+                .ok()
+                .and_then(|power| power.parse().ok())
+                .ok_or_else(|| self.unsupported())?;
+            exponent += power;
+        }
+        Ok(mantissa * 2f64.powi(exponent))
+    }
+}
+
+fn is_name_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(id: u32, entries: Vec<(LuaValue, LuaValue)>) -> LuaValue {
+        LuaValue::Table { id, entries }
+    }
+
+    fn string(value: &str) -> LuaValue {
+        LuaValue::String(value.to_string())
+    }
+
+    #[test]
+    fn test_values() {
+        let value = parse(
+            br#"do local _={1,nil,"a\"\n\\\65",[0x1.8p+2]=true,x=false,["y z"]=-0x1p-1,n=-12.5e1,{}};return _;end"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            table(
+                0,
+                vec![
+                    (LuaValue::Number(1.0), LuaValue::Number(1.0)),
+                    (LuaValue::Number(3.0), string("a\"\n\\A")),
+                    (LuaValue::Number(6.0), LuaValue::Bool(true)),
+                    (string("x"), LuaValue::Bool(false)),
+                    (string("y z"), LuaValue::Number(-0.5)),
+                    (string("n"), LuaValue::Number(-125.0)),
+                    (LuaValue::Number(4.0), table(1, Vec::new())),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_references() {
+        let value = parse(
+            b"do local _={a={b={}},c=nil};local __={};_.c=_.a.b;_.a.b.self=_;_.c.d=0x2p+0;return _;end",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            table(
+                0,
+                vec![
+                    (
+                        string("a"),
+                        table(
+                            1,
+                            vec![(
+                                string("b"),
+                                table(
+                                    2,
+                                    vec![
+                                        (string("self"), LuaValue::TableRef(0)),
+                                        (string("d"), LuaValue::Number(2.0)),
+                                    ]
+                                )
+                            )]
+                        )
+                    ),
+                    (string("c"), LuaValue::TableRef(2)),
+                ]
+            )
+        );
+
+        // tables as keys
+        let err =
+            parse(b"do local _={};local __={};__[_1]={};_[__[_1]]=1;return _;end").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Unsupported {
+                field: "lua source",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_function() {
+        let value = parse(
+            br"do local _={f=((loadstring or load)('\27Lua\0',[[@serialized]]))};return _;end",
+        );
+        assert!(value.is_err());
+
+        let value =
+            parse(br"do local _={f=((loadstring or load)('\27Lua\0','@serialized'))};return _;end")
+                .unwrap();
+        assert_eq!(
+            value.get("f"),
+            Some(&LuaValue::Function(b"\x1bLua\0".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let mut source = b"do local _=".to_vec();
+        source.extend(b"{".repeat(100_000));
+        let err = parse(&source).unwrap_err();
+        assert!(matches!(err, Error::TooDeep("lua value")));
+    }
+}
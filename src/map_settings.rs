@@ -0,0 +1,422 @@
+// The map generation settings and runtime map settings stored after the mod
+// settings in `level.dat`.
+// See https://wiki.factorio.com/Map_generator and the `map-gen-settings.example.json`
+// and `map-settings.example.json` files shipped with the game.
+use std::io::Read;
+
+use crate::{
+    reader::{
+        read_array, read_optional, read_string, with_capacity, CountingReader, FactorioReader,
+    },
+    saves::FactorioVersion,
+    Error, Result,
+};
+
+/// A position on a surface in tiles.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct MapPosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl MapPosition {
+    /// Positions are stored as fixed point numbers with 8 fractional bits,
+    /// either as a difference to the previously read position or in full.
+    fn read(
+        version: &FactorioVersion,
+        reader: &mut impl Read,
+        last: &mut MapPosition,
+    ) -> Result<Self> {
+        let dx = i16::read(version, reader)?;
+        let position = if dx == i16::MAX {
+            MapPosition {
+                x: i32::read(version, reader)? as f64 / 256.0,
+                y: i32::read(version, reader)? as f64 / 256.0,
+            }
+        } else {
+            let dy = i16::read(version, reader)?;
+            MapPosition {
+                x: last.x + dx as f64 / 256.0,
+                y: last.y + dy as f64 / 256.0,
+            }
+        };
+
+        *last = position;
+        Ok(position)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct AutoplaceControl {
+    pub name: String, // e.g. "iron-ore", "enemy-base" or since 2.0 "water"
+    pub frequency: f32,
+    pub size: f32,
+    pub richness: f32,
+}
+
+impl FactorioReader for AutoplaceControl {
+    fn read(version: &FactorioVersion, reader: &mut impl Read) -> Result<Self> {
+        Ok(AutoplaceControl {
+            name: read_string("autoplace control name", version, reader, false)?,
+            frequency: f32::read(version, reader)?,
+            size: f32::read(version, reader)?,
+            richness: f32::read(version, reader)?,
+        })
+    }
+}
+
+/// Per prototype type overrides of the autoplace settings of single
+/// prototypes, `autoplace_settings` in `map-gen-settings.json`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AutoplaceSettings {
+    pub prototype_type: String, // "entity", "tile" or "decorative"
+    pub treat_missing_as_default: bool,
+    pub settings: Vec<AutoplaceControl>,
+}
+
+impl FactorioReader for AutoplaceSettings {
+    fn read(version: &FactorioVersion, reader: &mut impl Read) -> Result<Self> {
+        Ok(AutoplaceSettings {
+            prototype_type: read_string("autoplace settings type", version, reader, false)?,
+            treat_missing_as_default: u8::read(version, reader)? != 0,
+            settings: read_array(version, reader)?,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct CliffSettings {
+    pub name: String,
+    pub control: Option<String>, // since 2.0, the autoplace control scaling the cliffs
+    pub cliff_elevation_0: f32,
+    pub cliff_elevation_interval: f32,
+    pub richness: f32,
+    pub cliff_smoothing: Option<f32>, // since 2.0
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct MapGenSettings {
+    pub terrain_segmentation: Option<f32>, // until 1.1
+    pub water: Option<f32>,                // until 1.1, an autoplace control since 2.0
+    pub autoplace_controls: Vec<AutoplaceControl>,
+    pub autoplace_settings: Vec<AutoplaceSettings>,
+    pub default_enable_all_autoplace_controls: bool,
+    pub seed: u32,
+    pub width: u32,  // 2000000 if unlimited
+    pub height: u32, // 2000000 if unlimited
+    pub area_to_generate_at_start: (MapPosition, MapPosition),
+    pub starting_area: f32,
+    pub peaceful_mode: bool,
+    pub no_enemies_mode: Option<bool>, // since 2.0
+    pub starting_points: Vec<MapPosition>,
+    pub property_expression_names: Vec<(String, String)>,
+    pub cliff_settings: CliffSettings,
+}
+
+impl MapGenSettings {
+    /// Only supports the format used since 0.17, older versions store most
+    /// settings as enums (`"very-low"` to `"very-high"`) instead of numbers.
+    pub(crate) fn read<R: Read>(
+        version: &FactorioVersion,
+        reader: &mut CountingReader<R>,
+    ) -> Result<Self> {
+        let v2 = version >= &[2, 0, 0, 0].into();
+        let mut last_position = MapPosition::default();
+
+        let (terrain_segmentation, water) = if v2 {
+            (None, None)
+        } else {
+            (
+                Some(f32::read(version, reader)?),
+                Some(f32::read(version, reader)?),
+            )
+        };
+
+        let autoplace_controls = read_array(version, reader)?;
+        let autoplace_settings = read_array(version, reader)?;
+        let default_enable_all_autoplace_controls = u8::read(version, reader)? != 0;
+        let seed = u32::read(version, reader)?;
+        let width = u32::read(version, reader)?;
+        let height = u32::read(version, reader)?;
+        let area_to_generate_at_start = (
+            MapPosition::read(version, reader, &mut last_position)?,
+            MapPosition::read(version, reader, &mut last_position)?,
+        );
+        // the rest of the bounding box, most likely its orientation
+        u32::read(version, reader)?;
+        let starting_area = f32::read(version, reader)?;
+        let peaceful_mode = u8::read(version, reader)? != 0;
+        let no_enemies_mode = if v2 {
+            Some(u8::read(version, reader)? != 0)
+        } else {
+            None
+        };
+        let starting_points = read_list(version, reader, |version, reader| {
+            MapPosition::read(version, reader, &mut last_position)
+        })?;
+        let property_expression_names = read_list(version, reader, |version, reader| {
+            Ok((
+                read_string("property expression name", version, reader, false)?,
+                read_string("property expression", version, reader, false)?,
+            ))
+        })?;
+
+        let cliff_settings = CliffSettings {
+            name: read_string("cliff name", version, reader, false)?,
+            control: if v2 {
+                Some(read_string("cliff control", version, reader, false)?)
+            } else {
+                None
+            },
+            cliff_elevation_0: f32::read(version, reader)?,
+            cliff_elevation_interval: f32::read(version, reader)?,
+            richness: f32::read(version, reader)?,
+            cliff_smoothing: if v2 {
+                Some(f32::read(version, reader)?)
+            } else {
+                None
+            },
+        };
+
+        Ok(MapGenSettings {
+            terrain_segmentation,
+            water,
+            autoplace_controls,
+            autoplace_settings,
+            default_enable_all_autoplace_controls,
+            seed,
+            width,
+            height,
+            area_to_generate_at_start,
+            starting_area,
+            peaceful_mode,
+            no_enemies_mode,
+            starting_points,
+            property_expression_names,
+            cliff_settings,
+        })
+    }
+}
+
+fn read_list<T, R: Read>(
+    version: &FactorioVersion,
+    reader: &mut R,
+    mut read: impl FnMut(&FactorioVersion, &mut R) -> Result<T>,
+) -> Result<Vec<T>> {
+    let length = u32::read_optimized(version, reader)?;
+    let mut res = with_capacity(length);
+    for _ in 0..length {
+        res.push(read(version, reader)?);
+    }
+    Ok(res)
+}
+
+// Every map setting is optional, `None` means the game's default is used.
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SteeringSettings {
+    pub radius: Option<f64>,
+    pub separation_factor: Option<f64>,
+    pub separation_force: Option<f64>,
+    pub force_unit_fuzzy_goto_behavior: Option<bool>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct EnemyEvolutionSettings {
+    pub enabled: Option<bool>,
+    pub time_factor: Option<f64>,
+    pub destroy_factor: Option<f64>,
+    pub pollution_factor: Option<f64>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct EnemyExpansionSettings {
+    pub enabled: Option<bool>,
+    pub max_expansion_distance: Option<u32>,
+    pub friendly_base_influence_radius: Option<u32>,
+    pub enemy_building_influence_radius: Option<u32>,
+    pub building_coefficient: Option<f64>,
+    pub other_base_coefficient: Option<f64>,
+    pub neighbouring_chunk_coefficient: Option<f64>,
+    pub neighbouring_base_chunk_coefficient: Option<f64>,
+    pub max_colliding_tiles_coefficient: Option<f64>,
+    pub settler_group_min_size: Option<u32>,
+    pub settler_group_max_size: Option<u32>,
+    pub min_expansion_cooldown: Option<u32>,
+    pub max_expansion_cooldown: Option<u32>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct UnitGroupSettings {
+    pub min_group_gathering_time: Option<u32>,
+    pub max_group_gathering_time: Option<u32>,
+    pub max_wait_time_for_late_members: Option<u32>,
+    pub max_group_radius: Option<f64>,
+    pub min_group_radius: Option<f64>,
+    pub max_member_speedup_when_behind: Option<f64>,
+    pub max_member_slowdown_when_ahead: Option<f64>,
+    pub max_group_slowdown_factor: Option<f64>,
+    pub max_group_member_fallback_factor: Option<f64>,
+    pub member_disown_distance: Option<f64>,
+    pub tick_tolerance_when_member_arrives: Option<u32>,
+    pub max_gathering_unit_groups: Option<u32>,
+    pub max_unit_group_size: Option<u32>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct PathFinderSettings {
+    pub fwd2bwd_ratio: Option<u32>,
+    pub goal_pressure_ratio: Option<f64>,
+    pub use_path_cache: Option<bool>,
+    pub max_steps_worked_per_tick: Option<f64>,
+    pub short_cache_size: Option<u32>,
+    pub long_cache_size: Option<u32>,
+    pub short_cache_min_cacheable_distance: Option<f64>,
+    pub short_cache_min_algo_steps_to_cache: Option<u32>,
+    pub long_cache_min_cacheable_distance: Option<f64>,
+    pub cache_max_connect_to_cache_steps_multiplier: Option<u32>,
+    pub cache_accept_path_start_distance_ratio: Option<f64>,
+    pub cache_accept_path_end_distance_ratio: Option<f64>,
+    pub negative_cache_accept_path_start_distance_ratio: Option<f64>,
+    pub negative_cache_accept_path_end_distance_ratio: Option<f64>,
+    pub cache_path_start_distance_rating_multiplier: Option<f64>,
+    pub cache_path_end_distance_rating_multiplier: Option<f64>,
+    pub stale_enemy_with_same_destination_collision_penalty: Option<f64>,
+    pub ignore_moving_enemy_collision_distance: Option<f64>,
+    pub enemy_with_different_destination_collision_penalty: Option<f64>,
+    pub general_entity_collision_penalty: Option<f64>,
+    pub general_entity_subsequent_collision_penalty: Option<f64>,
+    pub max_clients_to_accept_any_new_request: Option<u32>,
+    pub max_clients_to_accept_short_new_request: Option<u32>,
+    pub direct_distance_to_consider_short_request: Option<u32>,
+    pub short_request_max_steps: Option<u32>,
+    pub short_request_ratio: Option<f64>,
+    pub min_steps_to_check_path_find_termination: Option<u32>,
+    pub start_to_goal_cost_multiplier_to_terminate_path_find: Option<f64>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct MapSettings {
+    pub steering_default: SteeringSettings,
+    pub steering_moving: SteeringSettings,
+    pub enemy_evolution: EnemyEvolutionSettings,
+    pub enemy_expansion: EnemyExpansionSettings,
+    pub unit_group: UnitGroupSettings,
+    pub path_finder: PathFinderSettings,
+}
+
+impl MapSettings {
+    /// Only supports 0.17, which writes every setting explicitly. Later
+    /// versions only store the settings that differ from the defaults, in an
+    /// order we have not been able to map out yet.
+    pub(crate) fn read<R: Read>(
+        version: &FactorioVersion,
+        reader: &mut CountingReader<R>,
+    ) -> Result<Self> {
+        // 12 bytes which are the same in every save we have, likely the
+        // pollution settings. Their layout is unknown, so anything else is
+        // refused rather than possibly shifting every setting after them.
+        let offset = reader.position();
+        let mut unknown = [0; 12];
+        reader.read_exact(&mut unknown)?;
+        if unknown != [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] {
+            return Err(Error::Unsupported {
+                field: "pollution settings",
+                offset,
+            });
+        }
+
+        Ok(MapSettings {
+            steering_default: SteeringSettings::read(version, reader)?,
+            steering_moving: SteeringSettings::read(version, reader)?,
+            enemy_evolution: EnemyEvolutionSettings {
+                enabled: read_optional_bool(version, reader)?,
+                time_factor: read_optional(version, reader)?,
+                destroy_factor: read_optional(version, reader)?,
+                pollution_factor: read_optional(version, reader)?,
+            },
+            enemy_expansion: EnemyExpansionSettings {
+                enabled: read_optional_bool(version, reader)?,
+                max_expansion_distance: read_optional(version, reader)?,
+                friendly_base_influence_radius: read_optional(version, reader)?,
+                enemy_building_influence_radius: read_optional(version, reader)?,
+                building_coefficient: read_optional(version, reader)?,
+                other_base_coefficient: read_optional(version, reader)?,
+                neighbouring_chunk_coefficient: read_optional(version, reader)?,
+                neighbouring_base_chunk_coefficient: read_optional(version, reader)?,
+                max_colliding_tiles_coefficient: read_optional(version, reader)?,
+                settler_group_min_size: read_optional(version, reader)?,
+                settler_group_max_size: read_optional(version, reader)?,
+                min_expansion_cooldown: read_optional(version, reader)?,
+                max_expansion_cooldown: read_optional(version, reader)?,
+            },
+            unit_group: UnitGroupSettings {
+                min_group_gathering_time: read_optional(version, reader)?,
+                max_group_gathering_time: read_optional(version, reader)?,
+                max_wait_time_for_late_members: read_optional(version, reader)?,
+                max_group_radius: read_optional(version, reader)?,
+                min_group_radius: read_optional(version, reader)?,
+                max_member_speedup_when_behind: read_optional(version, reader)?,
+                max_member_slowdown_when_ahead: read_optional(version, reader)?,
+                max_group_slowdown_factor: read_optional(version, reader)?,
+                max_group_member_fallback_factor: read_optional(version, reader)?,
+                member_disown_distance: read_optional(version, reader)?,
+                tick_tolerance_when_member_arrives: read_optional(version, reader)?,
+                max_gathering_unit_groups: read_optional(version, reader)?,
+                max_unit_group_size: read_optional(version, reader)?,
+            },
+            path_finder: PathFinderSettings {
+                fwd2bwd_ratio: read_optional(version, reader)?,
+                goal_pressure_ratio: read_optional(version, reader)?,
+                use_path_cache: read_optional_bool(version, reader)?,
+                max_steps_worked_per_tick: read_optional(version, reader)?,
+                short_cache_size: read_optional(version, reader)?,
+                long_cache_size: read_optional(version, reader)?,
+                short_cache_min_cacheable_distance: read_optional(version, reader)?,
+                short_cache_min_algo_steps_to_cache: read_optional(version, reader)?,
+                long_cache_min_cacheable_distance: read_optional(version, reader)?,
+                cache_max_connect_to_cache_steps_multiplier: read_optional(version, reader)?,
+                cache_accept_path_start_distance_ratio: read_optional(version, reader)?,
+                cache_accept_path_end_distance_ratio: read_optional(version, reader)?,
+                negative_cache_accept_path_start_distance_ratio: read_optional(version, reader)?,
+                negative_cache_accept_path_end_distance_ratio: read_optional(version, reader)?,
+                cache_path_start_distance_rating_multiplier: read_optional(version, reader)?,
+                cache_path_end_distance_rating_multiplier: read_optional(version, reader)?,
+                stale_enemy_with_same_destination_collision_penalty: read_optional(
+                    version, reader,
+                )?,
+                ignore_moving_enemy_collision_distance: read_optional(version, reader)?,
+                enemy_with_different_destination_collision_penalty: read_optional(version, reader)?,
+                general_entity_collision_penalty: read_optional(version, reader)?,
+                general_entity_subsequent_collision_penalty: read_optional(version, reader)?,
+                max_clients_to_accept_any_new_request: read_optional(version, reader)?,
+                max_clients_to_accept_short_new_request: read_optional(version, reader)?,
+                direct_distance_to_consider_short_request: read_optional(version, reader)?,
+                short_request_max_steps: read_optional(version, reader)?,
+                short_request_ratio: read_optional(version, reader)?,
+                min_steps_to_check_path_find_termination: read_optional(version, reader)?,
+                start_to_goal_cost_multiplier_to_terminate_path_find: read_optional(
+                    version, reader,
+                )?,
+            },
+        })
+    }
+}
+
+impl SteeringSettings {
+    fn read<R: Read>(version: &FactorioVersion, reader: &mut CountingReader<R>) -> Result<Self> {
+        Ok(SteeringSettings {
+            radius: read_optional(version, reader)?,
+            separation_factor: read_optional(version, reader)?,
+            separation_force: read_optional(version, reader)?,
+            force_unit_fuzzy_goto_behavior: read_optional_bool(version, reader)?,
+        })
+    }
+}
+
+fn read_optional_bool<R: Read>(
+    version: &FactorioVersion,
+    reader: &mut CountingReader<R>,
+) -> Result<Option<bool>> {
+    Ok(read_optional::<u8, _>(version, reader)?.map(|value| value != 0))
+}
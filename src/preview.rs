@@ -0,0 +1,98 @@
+// Saves up to 0.16 ship a `preview.png`, newer ones a `preview.jpg`. Only the
+// image headers are parsed here, just enough to tell the dimensions.
+use std::io::Read;
+
+use crate::{error::Error, Result};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PreviewFormat {
+    Png,
+    Jpeg,
+}
+
+impl PreviewFormat {
+    /// The name of the preview entry in a save of this format.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            PreviewFormat::Png => "preview.png",
+            PreviewFormat::Jpeg => "preview.jpg",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            PreviewFormat::Png => "image/png",
+            PreviewFormat::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Preview {
+    pub format: PreviewFormat,
+    pub width: u32,
+    pub height: u32,
+    pub size: u64, // of the image file in bytes
+}
+
+/// Reads the dimensions of an image from its header, returns `(width, height)`.
+///
+/// Fails with [`Error::InvalidPreview`] if the header is not a valid image of
+/// `format`.
+pub fn read_dimensions(format: PreviewFormat, reader: &mut impl Read) -> Result<(u32, u32)> {
+    match format {
+        PreviewFormat::Png => read_png_dimensions(reader),
+        PreviewFormat::Jpeg => read_jpeg_dimensions(reader),
+    }
+}
+
+fn read_png_dimensions(reader: &mut impl Read) -> Result<(u32, u32)> {
+    // signature, then the IHDR chunk which always comes first
+    let mut buf = [0; 24];
+    reader.read_exact(&mut buf)?;
+    if buf[..8] != PNG_SIGNATURE || &buf[12..16] != b"IHDR" {
+        return Err(Error::InvalidPreview(PreviewFormat::Png));
+    }
+
+    Ok((
+        u32::from_be_bytes(buf[16..20].try_into().unwrap()),
+        u32::from_be_bytes(buf[20..24].try_into().unwrap()),
+    ))
+}
+
+fn read_jpeg_dimensions(reader: &mut impl Read) -> Result<(u32, u32)> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    if buf != [0xff, 0xd8] {
+        return Err(Error::InvalidPreview(PreviewFormat::Jpeg));
+    }
+
+    // skip segments until the start of frame, which holds the dimensions
+    loop {
+        let mut marker = [0; 4];
+        reader.read_exact(&mut marker)?;
+        if marker[0] != 0xff {
+            return Err(Error::InvalidPreview(PreviewFormat::Jpeg));
+        }
+        let length = u16::from_be_bytes([marker[2], marker[3]]) as u64;
+        if length < 2 {
+            return Err(Error::InvalidPreview(PreviewFormat::Jpeg));
+        }
+
+        // 0xc4, 0xc8 and 0xcc are other segments in the SOF range
+        if matches!(marker[1], 0xc0..=0xcf) && !matches!(marker[1], 0xc4 | 0xc8 | 0xcc) {
+            let mut frame = [0; 5];
+            reader.read_exact(&mut frame)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+            let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+            return Ok((width, height));
+        }
+
+        let skipped = std::io::copy(&mut reader.by_ref().take(length - 2), &mut std::io::sink())?;
+        if skipped != length - 2 {
+            return Err(Error::UnexpectedEof);
+        }
+    }
+}
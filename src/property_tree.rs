@@ -0,0 +1,273 @@
+// Factorio's generic tree format, used for mod settings, `mod-settings.dat`,
+// map settings and a few other places.
+// See https://wiki.factorio.com/Property_tree
+
+use std::io::{Read, Write};
+
+use crate::{
+    reader::{read_property_tree, CountingReader, FactorioReader},
+    saves::FactorioVersion,
+    writer::{write_property_tree, FactorioWriter},
+    Result,
+};
+
+/// A node of a property tree.
+#[derive(PartialEq, Debug, Clone)]
+pub struct PropertyTree {
+    pub value: PropertyTreeValue,
+    /// The "any-type" flag stored with every node since 0.17, Factorio uses it
+    /// when merging trees. Always `false` for older versions.
+    pub any_type: bool,
+}
+
+/// The value of a property tree node.
+///
+/// Dictionaries keep their entries in the order they were read, so they can
+/// be written back unchanged.
+#[derive(PartialEq, Debug, Clone)]
+pub enum PropertyTreeValue {
+    None,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<PropertyTree>),
+    Dictionary(Vec<(String, PropertyTree)>),
+    SignedInteger(i64),   // since 2.0
+    UnsignedInteger(u64), // since 2.0
+}
+
+impl From<PropertyTreeValue> for PropertyTree {
+    /// A node without the "any-type" flag.
+    fn from(value: PropertyTreeValue) -> Self {
+        PropertyTree {
+            value,
+            any_type: false,
+        }
+    }
+}
+
+impl PropertyTree {
+    /// Looks up `key` if this is a dictionary.
+    pub fn get(&self, key: &str) -> Option<&PropertyTree> {
+        match &self.value {
+            PropertyTreeValue::Dictionary(entries) => {
+                entries
+                    .iter()
+                    .find_map(|(k, v)| if k == key { Some(v) } else { None })
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads a tree in the encoding used by Factorio `version`.
+    ///
+    /// Before 0.17 the nodes have no "any-type" flag, from 2.0 on integer
+    /// nodes can appear.
+    pub fn read(version: &FactorioVersion, reader: &mut impl Read) -> Result<Self> {
+        read_property_tree(version, &mut CountingReader::new(reader))
+    }
+
+    /// Writes the tree in the encoding used by Factorio `version`, the inverse
+    /// of [`PropertyTree::read`].
+    pub fn write(&self, version: &FactorioVersion, writer: &mut impl Write) -> Result<()> {
+        write_property_tree(self, version, writer)
+    }
+}
+
+/// The contents of a `mod-settings.dat` file.
+///
+/// Only files written by Factorio >= 0.17 are supported, older ones don't
+/// start with a version and are just a bare [`PropertyTree`].
+#[derive(PartialEq, Debug, Clone)]
+pub struct ModSettings {
+    pub factorio_version: FactorioVersion,
+    pub quality_version: u8,
+    pub settings: PropertyTree, // "startup", "runtime-global" and "runtime-per-user"
+}
+
+impl ModSettings {
+    /// # Examples
+    ///
+    /// ```
+    /// use factorio::property_tree::{ModSettings, PropertyTreeValue};
+    ///
+    /// let settings = ModSettings {
+    ///     factorio_version: [1, 1, 110, 0].into(),
+    ///     quality_version: 0,
+    ///     settings: PropertyTreeValue::Dictionary(vec![]).into(),
+    /// };
+    /// let mut buf = Vec::new();
+    /// settings.write(&mut buf).unwrap();
+    ///
+    /// assert_eq!(ModSettings::read(&mut buf.as_slice()).unwrap(), settings);
+    /// ```
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        let reader = &mut CountingReader::new(reader);
+        let factorio_version = FactorioVersion::read(&[0, 0, 0, 0].into(), reader)?;
+        let quality_version = u8::read(&factorio_version, reader)?;
+        let settings = read_property_tree(&factorio_version, reader)?;
+
+        Ok(ModSettings {
+            factorio_version,
+            quality_version,
+            settings,
+        })
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        self.factorio_version
+            .write(&self.factorio_version, writer)?;
+        self.quality_version.write(&self.factorio_version, writer)?;
+        self.settings.write(&self.factorio_version, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn round_trip(version: FactorioVersion, tree: &PropertyTree) -> Vec<u8> {
+        let mut buf = Vec::new();
+        tree.write(&version, &mut buf).unwrap();
+        assert_eq!(
+            &PropertyTree::read(&version, &mut buf.as_slice()).unwrap(),
+            tree
+        );
+        buf
+    }
+
+    fn setting(value: PropertyTreeValue) -> PropertyTree {
+        PropertyTreeValue::Dictionary(vec![("value".to_string(), value.into())]).into()
+    }
+
+    #[test]
+    fn test_0_16() {
+        let tree = PropertyTreeValue::Dictionary(vec![(
+            "a".to_string(),
+            PropertyTreeValue::Bool(true).into(),
+        )])
+        .into();
+        // no any-type flag before 0.17
+        let expected = [5, 1, 0, 0, 0, 0, 1, b'a', 1, 1];
+        assert_eq!(round_trip([0, 16, 51, 0].into(), &tree), expected);
+    }
+
+    #[test]
+    fn test_0_17() {
+        let tree = PropertyTreeValue::Dictionary(vec![
+            ("bool".to_string(), setting(PropertyTreeValue::Bool(false))),
+            (
+                "number".to_string(),
+                setting(PropertyTreeValue::Number(1.5)),
+            ),
+            (
+                "string".to_string(),
+                setting(PropertyTreeValue::String("x".repeat(300))),
+            ),
+            (
+                "empty".to_string(),
+                setting(PropertyTreeValue::String(String::new())),
+            ),
+            (
+                "list".to_string(),
+                PropertyTreeValue::List(vec![
+                    PropertyTreeValue::None.into(),
+                    PropertyTreeValue::Number(-2.0).into(),
+                ])
+                .into(),
+            ),
+        ])
+        .into();
+        let buf = round_trip([0, 17, 79, 0].into(), &tree);
+        assert_eq!(buf[..2], [5, 0]);
+    }
+
+    #[test]
+    fn test_any_type() {
+        let tree = PropertyTree {
+            value: PropertyTreeValue::List(vec![PropertyTree {
+                value: PropertyTreeValue::Bool(true),
+                any_type: true,
+            }]),
+            any_type: true,
+        };
+        let buf = round_trip([1, 1, 0, 0].into(), &tree);
+        assert_eq!(buf, [4, 1, 1, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_2_0() {
+        let tree = PropertyTreeValue::Dictionary(vec![
+            (
+                "signed".to_string(),
+                setting(PropertyTreeValue::SignedInteger(-42)),
+            ),
+            (
+                "unsigned".to_string(),
+                setting(PropertyTreeValue::UnsignedInteger(u64::MAX)),
+            ),
+        ])
+        .into();
+        round_trip([2, 0, 13, 0].into(), &tree);
+    }
+
+    #[test]
+    fn test_invalid_type() {
+        let err = PropertyTree::read(
+            &[1, 1, 0, 0].into(),
+            &mut [5, 0, 1, 0, 0, 0, 1, 9, 0].as_slice(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidEnum {
+                field: "property tree type",
+                value: 9,
+                offset: 7,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_too_deep() {
+        // lists holding a single list each, without an end
+        let buf = [4, 0, 1, 0, 0, 0, 1].repeat(200_000);
+        let err = PropertyTree::read(&[1, 1, 0, 0].into(), &mut buf.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::TooDeep("property tree")));
+
+        let mut tree: PropertyTree = PropertyTreeValue::None.into();
+        for _ in 0..300 {
+            tree = PropertyTreeValue::List(vec![tree]).into();
+        }
+        let err = tree
+            .write(&[1, 1, 0, 0].into(), &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(err, Error::TooDeep("property tree")));
+    }
+
+    #[test]
+    fn test_mod_settings() {
+        let mut buf = vec![1, 0, 1, 0, 110, 0, 0, 0, 0];
+        buf.extend([5, 0, 1, 0, 0, 0]);
+        buf.extend([0, 7]);
+        buf.extend(b"startup");
+        buf.extend([5, 0, 1, 0, 0, 0]);
+        buf.extend([0, 4]);
+        buf.extend(b"test");
+        buf.extend([5, 0, 1, 0, 0, 0, 0, 5]);
+        buf.extend(b"value");
+        buf.extend([1, 0, 1]);
+
+        let settings = ModSettings::read(&mut buf.as_slice()).unwrap();
+        assert_eq!(settings.factorio_version, [1, 1, 110, 0].into());
+        assert_eq!(
+            settings.settings.get("startup").and_then(|s| s.get("test")),
+            Some(&setting(PropertyTreeValue::Bool(true)))
+        );
+
+        let mut written = Vec::new();
+        settings.write(&mut written).unwrap();
+        assert_eq!(written, buf);
+    }
+}
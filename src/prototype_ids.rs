@@ -0,0 +1,474 @@
+// Since 0.18 the level data holds, a little after the map settings, a table per
+// prototype family mapping every prototype name to the numeric ID the rest of
+// the level data refers to it by. Each family is stored as a count of
+// prototype types, then per type its name, a count and (name, ID) pairs. The
+// counts and IDs are u8, u16 or u32 depending on the family. The tables are
+// followed by the list of migration files that have been applied to the save.
+//
+// About 150 bytes between the map settings and the tables have not been
+// decoded yet, so the start of the tables can only be guessed: every offset in
+// that area is tried until the tables parse and the IDs of each family are
+// exactly 1..=n, which garbage never does. This is why reading them is called
+// a scan.
+use std::io::{self, Read};
+
+use crate::{
+    error::Error,
+    reader::{read_bytes, CountingReader, FactorioReader},
+    saves::{get_level_info, FactorioVersion},
+    Result,
+};
+
+/// The prototype families in the order they are stored for 0.18 to 1.1 saves,
+/// with the width in bytes of their counts and IDs.
+const FAMILIES_0_18: &[(&str, u8)] = &[
+    ("custom-input", 2),
+    ("equipment-grid", 1),
+    ("item", 2),
+    ("tile", 1),
+    ("decorative", 1),
+    ("technology", 2),
+    ("entity", 2),
+    ("particle", 2),
+    ("recipe-category", 2),
+    ("item-subgroup", 2),
+    ("item-group", 1),
+    ("fluid", 2),
+    ("virtual-signal", 2),
+    ("ammo-category", 1),
+    ("fuel-category", 1),
+    ("resource-category", 1),
+    ("equipment", 2),
+    ("noise-layer", 2),
+    ("noise-expression", 4),
+    ("autoplace-control", 1),
+    ("damage-type", 1),
+    ("recipe", 2),
+    ("achievement", 2),
+    ("module-category", 1),
+    ("equipment-category", 1),
+    ("mod-setting", 2),
+    ("trivial-smoke", 1),
+    ("shortcut", 2),
+];
+
+/// The prototype families of 2.0 saves, see [`FAMILIES_0_18`]. Later versions
+/// may add families, so they aren't read with this list.
+const FAMILIES_2_0: &[(&str, u8)] = &[
+    ("custom-input", 2),
+    ("equipment-grid", 1),
+    ("item", 2),
+    ("collision-layer", 2),
+    ("airborne-pollutant", 2),
+    ("tile", 2),
+    ("decorative", 2),
+    ("technology", 2),
+    ("entity", 2),
+    ("particle", 2),
+    ("recipe-category", 2),
+    ("item-subgroup", 2),
+    ("item-group", 1),
+    ("fluid", 2),
+    ("virtual-signal", 2),
+    ("ammo-category", 1),
+    ("fuel-category", 1),
+    ("resource-category", 1),
+    ("equipment", 2),
+    ("noise-function", 4),
+    ("noise-expression", 4),
+    ("autoplace-control", 1),
+    ("damage-type", 1),
+    ("recipe", 2),
+    ("achievement", 2),
+    ("module-category", 1),
+    ("equipment-category", 1),
+    ("mod-setting", 2),
+    ("trivial-smoke", 1),
+    ("asteroid-chunk", 2),
+    ("quality", 1),
+    ("surface-property", 2),
+    ("procession-layer-inheritance-group", 1),
+    ("procession", 2),
+    ("space-location", 2),
+    ("space-connection", 2),
+    ("active-trigger", 2),
+    ("shortcut", 2),
+    ("burner-usage", 2),
+    ("surface", 2),
+];
+
+/// How far past the map settings the tables are looked for.
+const MAX_SKIP: usize = 4096;
+
+/// How many bytes a single attempt at reading the tables may buffer, big mod
+/// packs need a few megabytes. Attempts at a wrong offset can read a lot of
+/// garbage before failing and are given up at this point.
+const MAX_BUFFERED: usize = 32 * 1024 * 1024;
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Prototype {
+    pub id: u32,
+    /// e.g. `assembling-machine` for the `entity` family.
+    pub prototype_type: String,
+    pub name: String,
+}
+
+/// The prototypes of one family, e.g. all entities. IDs are unique within a
+/// family, not across families.
+#[derive(PartialEq, Debug, Clone)]
+pub struct PrototypeFamily {
+    pub name: &'static str,
+    /// Sorted by ID, which run from 1 to the number of prototypes.
+    pub prototypes: Vec<Prototype>,
+}
+
+impl PrototypeFamily {
+    pub fn get(&self, id: u32) -> Option<&Prototype> {
+        let index = id.checked_sub(1)?;
+        self.prototypes.get(index as usize)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Prototype> {
+        self.prototypes
+            .iter()
+            .find(|prototype| prototype.name == name)
+    }
+}
+
+/// A migration file that has been applied to the save.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Migration {
+    pub mod_name: String,
+    pub file: String,
+}
+
+/// The numeric IDs prototypes are referred to by in the level data.
+///
+/// # Examples
+///
+/// ```
+/// use factorio::save_file::SaveFile;
+///
+/// let file = std::fs::File::open("test/test_1_1.zip").unwrap();
+/// let ids = SaveFile::new(file).unwrap().scan_prototype_ids().unwrap();
+/// let technology = ids.family("technology").unwrap();
+/// assert_eq!(technology.get(1).unwrap().name, "belt-balancer-1");
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct PrototypeIdMap {
+    pub families: Vec<PrototypeFamily>,
+    pub migrations: Vec<Migration>,
+}
+
+impl PrototypeIdMap {
+    /// Reads the header and level info from a reader to the level data, see
+    /// [`get_level_info`], then searches the following bytes for the
+    /// prototype ID tables.
+    ///
+    /// Fails with [`Error::UnsupportedVersion`] for saves older than 0.18 or
+    /// newer than 2.0, with [`Error::UnexpectedEof`] if the level data ends
+    /// before the tables were found and with [`Error::Unsupported`] if they
+    /// can't be found otherwise. Errors of the reader are passed on.
+    pub fn scan(reader: &mut impl Read) -> Result<Self> {
+        let reader = &mut CountingReader::new(reader);
+        let version = get_level_info(reader)?.header.factorio_version;
+        let families = if version >= [0, 18, 0, 0].into() && version < [1, 2, 0, 0].into() {
+            FAMILIES_0_18
+        } else if version >= [2, 0, 0, 0].into() && version < [2, 1, 0, 0].into() {
+            FAMILIES_2_0
+        } else {
+            return Err(Error::UnsupportedVersion(version));
+        };
+
+        Self::find_tables(&version, families, reader)
+    }
+
+    /// Tries to read the tables at every offset up to [`MAX_SKIP`] bytes
+    /// ahead of the current position of `reader`.
+    fn find_tables<R: Read>(
+        version: &FactorioVersion,
+        families: &[(&'static str, u8)],
+        reader: &mut CountingReader<R>,
+    ) -> Result<Self> {
+        let offset = reader.position();
+        let mut lookahead = Lookahead {
+            inner: reader,
+            buf: Vec::new(),
+            start: 0,
+            position: 0,
+        };
+        let mut ended = false;
+        for skip in 0..MAX_SKIP {
+            lookahead.restart_at(skip);
+            match Self::read_tables(version, families, &mut lookahead) {
+                Ok(Some(map)) => return Ok(map),
+                Ok(None) | Err(Error::TooLarge(_)) => {}
+                // a wrong offset can make a huge count run past the end
+                Err(Error::UnexpectedEof) => ended = true,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if ended {
+            return Err(Error::UnexpectedEof);
+        }
+        Err(Error::Unsupported {
+            field: "prototype ids",
+            offset,
+        })
+    }
+
+    /// Returns `None` if the data doesn't look like prototype ID tables.
+    fn read_tables(
+        version: &FactorioVersion,
+        families: &[(&'static str, u8)],
+        reader: &mut impl Read,
+    ) -> Result<Option<Self>> {
+        let mut res = Vec::with_capacity(families.len());
+        for &(name, width) in families {
+            let mut prototypes = Vec::new();
+            let Some(type_count) = read_count(version, reader, width)? else {
+                return Ok(None);
+            };
+            for _ in 0..type_count {
+                let Some(prototype_type) = read_name(version, reader)? else {
+                    return Ok(None);
+                };
+                let Some(count) = read_count(version, reader, width)? else {
+                    return Ok(None);
+                };
+                for _ in 0..count {
+                    let Some(name) = read_name(version, reader)? else {
+                        return Ok(None);
+                    };
+                    let Some(id) = read_count(version, reader, width)? else {
+                        return Ok(None);
+                    };
+                    prototypes.push(Prototype {
+                        id,
+                        prototype_type: prototype_type.clone(),
+                        name,
+                    });
+                }
+            }
+
+            prototypes.sort_by_key(|prototype| prototype.id);
+            if prototypes
+                .iter()
+                .zip(1..)
+                .any(|(prototype, id)| prototype.id != id)
+            {
+                return Ok(None);
+            }
+            res.push(PrototypeFamily { name, prototypes });
+        }
+
+        // a run of zeros parses as empty tables, but every game has entities
+        if res
+            .iter()
+            .any(|family| family.name == "entity" && family.prototypes.is_empty())
+        {
+            return Ok(None);
+        }
+
+        // 2.0 saves have two more bytes here which are zero in every save we
+        // have, we don't know what they hold otherwise
+        if version >= &[2, 0, 0, 0].into() && u16::read(version, reader)? != 0 {
+            return Ok(None);
+        }
+
+        let count = u32::read_optimized(version, reader)?;
+        let mut migrations = Vec::new();
+        for _ in 0..count {
+            let (Some(mod_name), Some(file)) =
+                (read_name(version, reader)?, read_name(version, reader)?)
+            else {
+                return Ok(None);
+            };
+            migrations.push(Migration { mod_name, file });
+        }
+
+        Ok(Some(Self {
+            families: res,
+            migrations,
+        }))
+    }
+
+    pub fn family(&self, name: &str) -> Option<&PrototypeFamily> {
+        self.families.iter().find(|family| family.name == name)
+    }
+}
+
+/// Reads a count or ID, `None` if it is implausibly large.
+fn read_count(version: &FactorioVersion, reader: &mut impl Read, width: u8) -> Result<Option<u32>> {
+    let value = match width {
+        1 => u8::read(version, reader)? as u32,
+        2 => u16::read(version, reader)? as u32,
+        _ => u32::read(version, reader)?,
+    };
+    Ok((value <= u16::MAX as u32).then_some(value))
+}
+
+/// Reads a prototype or file name, `None` if it is empty, too long or not
+/// UTF-8, so that garbage doesn't make us read huge strings.
+fn read_name(version: &FactorioVersion, reader: &mut impl Read) -> Result<Option<String>> {
+    let mut length = [0];
+    reader.read_exact(&mut length)?;
+    if length[0] == 0 || length[0] == u8::MAX {
+        return Ok(None);
+    }
+
+    let value = read_bytes(version, &mut (&length[..]).chain(reader), true)?;
+    Ok(String::from_utf8(value).ok())
+}
+
+/// Keeps what has been read from `inner` since the start of the current
+/// attempt, so that the next one can restart at a later position. Fails with
+/// [`Error::TooLarge`] once an attempt would buffer more than
+/// [`MAX_BUFFERED`] bytes.
+struct Lookahead<R> {
+    inner: R,
+    buf: Vec<u8>,
+    /// The position in `inner` of the first byte in `buf`.
+    start: usize,
+    /// The position in `buf` reading continues from.
+    position: usize,
+}
+
+impl<R> Lookahead<R> {
+    /// Continues reading at position `offset` of `inner`, which must not be
+    /// before the start of the previous attempt.
+    fn restart_at(&mut self, offset: usize) {
+        let dropped = (offset - self.start).min(self.buf.len());
+        self.buf.drain(..dropped);
+        self.start += dropped;
+        self.position = offset - self.start;
+    }
+}
+
+impl<R: Read> Read for Lookahead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.buf.len() {
+            if self.buf.len() >= MAX_BUFFERED {
+                return Err(io::Error::other(Error::TooLarge(MAX_BUFFERED as u64)));
+            }
+
+            let start = self.buf.len();
+            self.buf.resize(start + buf.len().max(8192), 0);
+            let read = match self.inner.read(&mut self.buf[start..]) {
+                Ok(read) => read,
+                Err(e) => {
+                    self.buf.truncate(start);
+                    return Err(e);
+                }
+            };
+            self.buf.truncate(start + read);
+            if read == 0 {
+                return Ok(0);
+            }
+        }
+
+        let read = (&self.buf[self.position..]).read(buf)?;
+        self.position += read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::save_file::SaveFile;
+
+    fn prototype_ids(path: &str) -> Result<PrototypeIdMap> {
+        SaveFile::new(File::open(path).unwrap())?.scan_prototype_ids()
+    }
+
+    #[test]
+    fn test_1_1() {
+        let ids = prototype_ids("test/test_1_1.zip").unwrap();
+        assert_eq!(ids.families.len(), FAMILIES_0_18.len());
+        assert_eq!(ids.family("technology").unwrap().prototypes.len(), 195);
+        assert_eq!(ids.family("recipe").unwrap().prototypes.len(), 215);
+        assert!(ids.family("mod-setting").unwrap().prototypes.is_empty());
+
+        let entity = ids.family("entity").unwrap();
+        let assembler = entity.find("assembling-machine-1").unwrap();
+        assert_eq!(assembler.prototype_type, "assembling-machine");
+        assert_eq!(entity.get(assembler.id), Some(assembler));
+        assert_eq!(entity.get(0), None);
+
+        assert_eq!(
+            ids.migrations.first(),
+            Some(&Migration {
+                mod_name: "base".to_string(),
+                file: "1.1.0.json".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_0_18() {
+        let ids = prototype_ids("test/test_0_18.zip").unwrap();
+        assert_eq!(ids.family("technology").unwrap().prototypes.len(), 232);
+        assert_eq!(
+            ids.family("technology").unwrap().get(1).unwrap().name,
+            "belt-balancer-1"
+        );
+        assert_eq!(ids.family("entity").unwrap().prototypes.len(), 500);
+    }
+
+    #[test]
+    fn test_2_0() {
+        let ids = prototype_ids("test/test_2_0_13_ext.zip").unwrap();
+        assert_eq!(ids.families.len(), FAMILIES_2_0.len());
+        let quality: Vec<_> = ids
+            .family("quality")
+            .unwrap()
+            .prototypes
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(quality.len(), 6);
+        assert!(quality.contains(&"legendary"));
+        assert_eq!(ids.migrations.len(), 13);
+
+        prototype_ids("test/test_2_0_13.zip").unwrap();
+        prototype_ids("test/test_1_1_14.zip").unwrap();
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut save = SaveFile::new(File::open("test/test_1_1.zip").unwrap()).unwrap();
+        let mut data = Vec::new();
+        save.level_data().unwrap().read_to_end(&mut data).unwrap();
+
+        // ends in the middle of the tables
+        data.truncate(2000);
+        let err = PrototypeIdMap::scan(&mut data.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedEof), "{err:?}");
+    }
+
+    #[test]
+    fn test_candidate_past_end() {
+        // at offset 0 the second migration's name runs past the end, at
+        // offset 1 there is one migration
+        let data = [2, 1, 1, b'a', 1, b'b'];
+        let reader = &mut CountingReader::new(data.as_slice());
+        let ids = PrototypeIdMap::find_tables(&[1, 1, 0, 0].into(), &[], reader).unwrap();
+        assert_eq!(
+            ids.migrations,
+            [Migration {
+                mod_name: "a".to_string(),
+                file: "b".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let err = prototype_ids("test/test_0_17.zip").unwrap_err();
+        assert!(matches!(err, Error::UnsupportedVersion(_)));
+    }
+}
@@ -1,10 +1,15 @@
 use std::{io, io::Read};
 
-use crate::saves::FactorioVersion;
+use crate::{
+    error::Error,
+    property_tree::{PropertyTree, PropertyTreeValue},
+    saves::FactorioVersion,
+    Result,
+};
 
 pub(crate) trait FactorioReader: Sized {
-    fn read(version: &FactorioVersion, reader: &mut impl Read) -> io::Result<Self>;
-    fn read_optimized(_version: &FactorioVersion, _reader: &mut impl Read) -> io::Result<Self> {
+    fn read(version: &FactorioVersion, reader: &mut impl Read) -> Result<Self>;
+    fn read_optimized(_version: &FactorioVersion, _reader: &mut impl Read) -> Result<Self> {
         unimplemented!();
     }
 }
@@ -12,14 +17,13 @@ pub(crate) trait FactorioReader: Sized {
 macro_rules! read_num_impl {
     ($int:ty) => {
         impl FactorioReader for $int {
-            fn read(_version: &FactorioVersion, reader: &mut impl Read) -> io::Result<Self> {
-                let buf: $int = 0;
-                let mut buf = buf.to_le_bytes();
+            fn read(_version: &FactorioVersion, reader: &mut impl Read) -> Result<Self> {
+                let mut buf = [0; std::mem::size_of::<$int>()];
                 reader.read_exact(&mut buf)?;
                 Ok(<$int>::from_le_bytes(buf))
             }
 
-            fn read_optimized(version: &FactorioVersion, reader: &mut impl Read) -> io::Result<Self> {
+            fn read_optimized(version: &FactorioVersion, reader: &mut impl Read) -> Result<Self> {
                 let first = u8::read(version, reader)?;
                 if first != u8::MAX {
                     return Ok(first.into());
@@ -33,29 +37,85 @@ macro_rules! read_num_impl {
     ($($int:ty),*) => {$(read_num_impl!($int);)*}
 }
 
-read_num_impl!(u8, u16, u32, u64);
+read_num_impl!(u8, u16, u32, u64, i16, i32, i64, f32, f64);
 
 pub(crate) fn read_string(
+    field: &'static str,
     version: &FactorioVersion,
     reader: &mut impl Read,
     force_optimized: bool,
-) -> io::Result<String> {
+) -> Result<String> {
+    let buf = read_bytes(version, reader, force_optimized)?;
+    String::from_utf8(buf).map_err(|source| Error::InvalidUtf8 { field, source })
+}
+
+/// Reads a length prefixed byte string, the length is stored like the one of
+/// strings.
+pub(crate) fn read_bytes(
+    version: &FactorioVersion,
+    reader: &mut impl Read,
+    force_optimized: bool,
+) -> Result<Vec<u8>> {
     let length = if version >= &[0, 16, 0, 0].into() || force_optimized {
         u32::read_optimized(version, reader)?
     } else {
         u32::read(version, reader)?
     };
 
-    let mut buf = vec![0; length as _];
-    reader.read_exact(&mut buf)?;
+    // read in pieces, a corrupt length must not allocate gigabytes up front
+    let mut buf = with_capacity(length);
+    reader.take(length as u64).read_to_end(&mut buf)?;
+    if buf.len() != length as usize {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(buf)
+}
 
-    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+/// Creates a vector for `length` elements read from a file. Lengths come from
+/// untrusted input, so only a limited capacity is reserved up front and the
+/// vector grows as the elements are actually read.
+pub(crate) fn with_capacity<T>(length: u32) -> Vec<T> {
+    const MAX_CAPACITY: usize = 4096;
+    Vec::with_capacity((length as usize).min(MAX_CAPACITY))
+}
+
+/// Reads a single byte and converts it into `T`, reporting the field name and
+/// the position of the byte if it is not a known value.
+pub(crate) fn read_enum<T: TryFrom<u8>, R: Read>(
+    field: &'static str,
+    version: &FactorioVersion,
+    reader: &mut CountingReader<R>,
+) -> Result<T> {
+    let offset = reader.position();
+    let value = u8::read(version, reader)?;
+    T::try_from(value).map_err(|_| Error::InvalidEnum {
+        field,
+        value,
+        offset,
+    })
+}
+
+/// Reads a value prefixed by a flag telling whether it is present.
+pub(crate) fn read_optional<T: FactorioReader, R: Read>(
+    version: &FactorioVersion,
+    reader: &mut CountingReader<R>,
+) -> Result<Option<T>> {
+    let offset = reader.position();
+    match u8::read(version, reader)? {
+        0 => Ok(None),
+        1 => Ok(Some(T::read(version, reader)?)),
+        value => Err(Error::InvalidEnum {
+            field: "optional flag",
+            value,
+            offset,
+        }),
+    }
 }
 
 pub(crate) fn read_quality_version(
     version: &FactorioVersion,
     reader: &mut impl Read,
-) -> io::Result<Option<u8>> {
+) -> Result<Option<u8>> {
     if version >= &[0, 17, 0, 0].into() {
         return Ok(Some(u8::read(version, reader)?));
     }
@@ -65,7 +125,7 @@ pub(crate) fn read_quality_version(
 pub(crate) fn read_allow_non_admin_debug_options(
     version: &FactorioVersion,
     reader: &mut impl Read,
-) -> io::Result<Option<bool>> {
+) -> Result<Option<bool>> {
     if version >= &[0, 16, 0, 0].into() {
         return Ok(Some(u8::read(version, reader)? != 0));
     }
@@ -75,7 +135,7 @@ pub(crate) fn read_allow_non_admin_debug_options(
 pub(crate) fn read_loaded_from(
     version: &FactorioVersion,
     reader: &mut impl Read,
-) -> io::Result<[u16; 3]> {
+) -> Result<[u16; 3]> {
     if version >= &[0, 14, 14, 0].into() {
         Ok([
             u16::read_optimized(version, reader)?,
@@ -94,14 +154,14 @@ pub(crate) fn read_loaded_from(
 pub(crate) fn read_array<T: FactorioReader>(
     version: &FactorioVersion,
     reader: &mut impl Read,
-) -> io::Result<Vec<T>> {
+) -> Result<Vec<T>> {
     let length = if version >= &[0, 16, 0, 0].into() {
         u32::read_optimized(version, reader)?
     } else {
         u32::read(version, reader)?
     };
 
-    let mut res = Vec::with_capacity(length as _);
+    let mut res = with_capacity(length);
     for _ in 0..length {
         let val = T::read(version, reader)?;
         res.push(val);
@@ -110,28 +170,135 @@ pub(crate) fn read_array<T: FactorioReader>(
     Ok(res)
 }
 
-pub(crate) fn read_build_version(
+pub(crate) fn read_build_version(version: &FactorioVersion, reader: &mut impl Read) -> Result<u32> {
+    Ok(if version >= &FactorioVersion::from([2, 0, 0, 0]) {
+        u32::read(version, reader)?
+    } else {
+        u16::read(version, reader)? as u32
+    })
+}
+
+pub(crate) fn read_large_blueprint_size(
     version: &FactorioVersion,
     reader: &mut impl Read,
-) -> io::Result<u32> {
-    Ok(
-        if version >= &FactorioVersion::from([2, 0, 0, 0]) {
-            u32::read(version, reader)?
-        } else {
-            u16::read(&version, reader)? as u32
-        }
-    )
+) -> Result<Option<u32>> {
+    Ok(if version >= &[2, 0, 0, 0].into() {
+        Some(u32::read(version, reader)?)
+    } else {
+        None
+    })
 }
 
-pub(crate) fn read_large_blueprint_size(
+pub(crate) fn read_mod_settings_crc(
     version: &FactorioVersion,
     reader: &mut impl Read,
-) -> io::Result<Option<u32>> {
-    Ok(
-        if version >= &[2, 0, 0, 0].into() {
-            Some(u32::read(version, reader)?)
-        } else {
-            None
+) -> Result<Option<u32>> {
+    if version >= &[0, 16, 0, 0].into() {
+        return Ok(Some(u32::read(version, reader)?));
+    }
+    Ok(None)
+}
+
+pub(crate) fn read_startup_mod_settings<R: Read>(
+    version: &FactorioVersion,
+    reader: &mut CountingReader<R>,
+) -> Result<Option<PropertyTree>> {
+    if version >= &[0, 16, 0, 0].into() {
+        return Ok(Some(read_property_tree(version, reader)?));
+    }
+    Ok(None)
+}
+
+/// How deep property trees may nest. Factorio's own trees are only a few
+/// levels deep, the limit keeps corrupt input from overflowing the stack.
+pub(crate) const MAX_PROPERTY_TREE_DEPTH: usize = 256;
+
+pub(crate) fn read_property_tree<R: Read>(
+    version: &FactorioVersion,
+    reader: &mut CountingReader<R>,
+) -> Result<PropertyTree> {
+    read_property_tree_node(version, reader, 0)
+}
+
+fn read_property_tree_node<R: Read>(
+    version: &FactorioVersion,
+    reader: &mut CountingReader<R>,
+    depth: usize,
+) -> Result<PropertyTree> {
+    if depth > MAX_PROPERTY_TREE_DEPTH {
+        return Err(Error::TooDeep("property tree"));
+    }
+
+    let offset = reader.position();
+    let kind = u8::read(version, reader)?;
+    let any_type = version >= &[0, 17, 0, 0].into() && u8::read(version, reader)? != 0;
+
+    let value = match kind {
+        0 => PropertyTreeValue::None,
+        1 => PropertyTreeValue::Bool(u8::read(version, reader)? != 0),
+        2 => PropertyTreeValue::Number(f64::read(version, reader)?),
+        3 => PropertyTreeValue::String(read_property_tree_string(version, reader)?),
+        4 => {
+            let length = u32::read(version, reader)?;
+            let mut res = with_capacity(length);
+            for _ in 0..length {
+                // list entries are stored with an (empty) key as well
+                read_property_tree_string(version, reader)?;
+                res.push(read_property_tree_node(version, reader, depth + 1)?);
+            }
+            PropertyTreeValue::List(res)
+        }
+        5 => {
+            let length = u32::read(version, reader)?;
+            let mut res = with_capacity(length);
+            for _ in 0..length {
+                let key = read_property_tree_string(version, reader)?;
+                res.push((key, read_property_tree_node(version, reader, depth + 1)?));
+            }
+            PropertyTreeValue::Dictionary(res)
+        }
+        6 => PropertyTreeValue::SignedInteger(i64::read(version, reader)?),
+        7 => PropertyTreeValue::UnsignedInteger(u64::read(version, reader)?),
+        _ => {
+            return Err(Error::InvalidEnum {
+                field: "property tree type",
+                value: kind,
+                offset,
+            })
         }
-    )
+    };
+    Ok(PropertyTree { value, any_type })
+}
+
+fn read_property_tree_string(version: &FactorioVersion, reader: &mut impl Read) -> Result<String> {
+    let empty = u8::read(version, reader)? != 0;
+    if empty {
+        return Ok(String::new());
+    }
+    read_string("property tree string", version, reader, true)
+}
+
+/// Wraps a reader and keeps track of how many bytes have been read from it, so
+/// errors can point at the offending byte.
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
 }
@@ -0,0 +1,716 @@
+// A save is a zip archive with a single root folder, named after the save when
+// it was first created, containing the level data, the scenario's Lua files,
+// locale folders and a few smaller files.
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, Write},
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    error::Error,
+    level_data::{LevelDataFile, LevelDataLayout, LevelDataMetadata, LevelDataReader},
+    map_settings::MapSettings,
+    preview::{read_dimensions, Preview, PreviewFormat},
+    prototype_ids::PrototypeIdMap,
+    saves::{get_level_info, get_map_settings, get_save_header, LevelInfo, SaveHeader},
+    script_data::ScriptData,
+    source::{SaveSource, SourceFile},
+    Result,
+};
+
+/// An opened save archive, or any other [`SaveSource`] holding the files of a
+/// save.
+///
+/// All paths taken and returned by its methods are relative to the root
+/// folder of the archive. The methods reading the level data read `level.dat`
+/// unless `level-init.dat` is selected with [`SaveFile::select_level_data`].
+///
+/// # Examples
+///
+/// ```
+/// use factorio::save_file::SaveFile;
+///
+/// let file = std::fs::File::open("test/test_1_1_14.zip").unwrap();
+/// let mut save = SaveFile::new(file).unwrap();
+/// assert_eq!(save.root(), "test1_1_14");
+///
+/// let header = save.header().unwrap();
+/// let control = save.control_lua().unwrap();
+/// ```
+pub struct SaveFile<S> {
+    source: S,
+    root: String,
+    level_data: LevelDataLayout,
+    level_data_file: LevelDataFile,
+}
+
+impl<R: Read + Seek> SaveFile<ZipArchive<R>> {
+    /// Opens the save zip archive read by `reader`.
+    ///
+    /// Fails with [`Error::MissingLevelDat`] if the archive is not a save.
+    pub fn new(reader: R) -> Result<Self> {
+        Self::from_source(ZipArchive::new(reader)?)
+    }
+}
+
+impl<S: SaveSource> SaveFile<S> {
+    /// Fails with [`Error::MissingLevelDat`] if `source` is not a save.
+    pub fn from_source(mut source: S) -> Result<Self> {
+        let level_data = LevelDataLayout::find(&mut source)?;
+
+        let level_dat = match &level_data {
+            LevelDataLayout::Single(name) => name,
+            LevelDataLayout::Chunked { chunks, .. } => &chunks[0],
+        };
+        let root = match level_dat.rsplit_once('/') {
+            Some((root, _)) => root.to_string(),
+            None => String::new(),
+        };
+
+        Ok(Self {
+            source,
+            root,
+            level_data,
+            level_data_file: LevelDataFile::Level,
+        })
+    }
+
+    /// Switches the file [`SaveFile::header`], [`SaveFile::level_info`] and the
+    /// other methods reading the level data read from.
+    ///
+    /// Fails with [`Error::MissingLevelInitDat`] if `file` is
+    /// [`LevelDataFile::LevelInit`] and the save has no `level-init.dat`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use factorio::{level_data::LevelDataFile, save_file::SaveFile};
+    ///
+    /// let file = std::fs::File::open("test/test_1_1.zip").unwrap();
+    /// let mut save = SaveFile::new(file).unwrap();
+    /// save.select_level_data(LevelDataFile::LevelInit).unwrap();
+    /// assert_eq!(save.level_info().unwrap().ticks_played, Some(0));
+    /// ```
+    pub fn select_level_data(&mut self, file: LevelDataFile) -> Result<()> {
+        self.level_data = match file {
+            LevelDataFile::Level => LevelDataLayout::find(&mut self.source)?,
+            LevelDataFile::LevelInit => {
+                let name = self.path("level-init.dat");
+                if !self.source.contains(&name) {
+                    return Err(Error::MissingLevelInitDat);
+                }
+                LevelDataLayout::Single(name)
+            }
+        };
+        self.level_data_file = file;
+        Ok(())
+    }
+
+    /// The file the level data is currently read from.
+    pub fn level_data_file(&self) -> LevelDataFile {
+        self.level_data_file
+    }
+
+    /// The name of the folder everything in the archive is stored in, e.g.
+    /// `test_1_1`.
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    pub fn header(&mut self) -> Result<SaveHeader> {
+        get_save_header(&mut self.level_data()?)
+    }
+
+    /// See [`get_level_info`].
+    pub fn level_info(&mut self) -> Result<LevelInfo> {
+        get_level_info(&mut self.level_data()?)
+    }
+
+    /// See [`get_map_settings`].
+    pub fn map_settings(&mut self) -> Result<MapSettings> {
+        get_map_settings(&mut self.level_data()?)
+    }
+
+    /// See [`PrototypeIdMap::scan`].
+    pub fn scan_prototype_ids(&mut self) -> Result<PrototypeIdMap> {
+        PrototypeIdMap::scan(&mut self.level_data()?)
+    }
+
+    /// The decompressed contents of `level.dat`, see [`LevelDataReader`].
+    pub fn level_data(&mut self) -> Result<LevelDataReader<'_, S>> {
+        Ok(LevelDataReader::with_layout(
+            &mut self.source,
+            self.level_data.clone(),
+        ))
+    }
+
+    pub fn level_data_layout(&self) -> &LevelDataLayout {
+        &self.level_data
+    }
+
+    /// All files in the archive.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        let prefix = if self.root.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.root)
+        };
+        self.source
+            .file_names()
+            .filter_map(move |name| name.strip_prefix(prefix.as_str()))
+            .filter(|name| !name.is_empty() && !name.ends_with('/'))
+    }
+
+    /// The scenario's Lua files, `control.lua` and everything it requires.
+    pub fn lua_files(&self) -> impl Iterator<Item = &str> {
+        self.files().filter(|name| name.ends_with(".lua"))
+    }
+
+    /// The files in the `locale` and `campaign-locale` folders.
+    pub fn locale_files(&self) -> impl Iterator<Item = &str> {
+        self.files()
+            .filter(|name| name.starts_with("locale/") || name.starts_with("campaign-locale/"))
+    }
+
+    /// Reads the file at `path`, returns `None` if it does not exist.
+    pub fn read_file(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        let name = self.path(path);
+        let Some(mut file) = self.source.open(&name)? else {
+            return Ok(None);
+        };
+
+        // the size comes from the archive, so it isn't reserved up front
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        Ok(Some(buf))
+    }
+
+    fn read_text_file(&mut self, path: &'static str) -> Result<Option<String>> {
+        self.read_file(path)?
+            .map(|buf| {
+                String::from_utf8(buf).map_err(|source| Error::InvalidUtf8 {
+                    field: path,
+                    source,
+                })
+            })
+            .transpose()
+    }
+
+    pub fn control_lua(&mut self) -> Result<Option<String>> {
+        self.read_text_file("control.lua")
+    }
+
+    pub fn info_json(&mut self) -> Result<Option<String>> {
+        self.read_text_file("info.json")
+    }
+
+    pub fn script_dat(&mut self) -> Result<Option<Vec<u8>>> {
+        self.read_file("script.dat")
+    }
+
+    pub fn script_init_dat(&mut self) -> Result<Option<Vec<u8>>> {
+        self.read_file("script-init.dat")
+    }
+
+    /// `script.dat` parsed, see [`ScriptData`].
+    pub fn script_data(&mut self) -> Result<Option<ScriptData>> {
+        self.script_dat()?
+            .map(|buf| ScriptData::read(&mut buf.as_slice()))
+            .transpose()
+    }
+
+    pub fn script_init_data(&mut self) -> Result<Option<ScriptData>> {
+        self.script_init_dat()?
+            .map(|buf| ScriptData::read(&mut buf.as_slice()))
+            .transpose()
+    }
+
+    pub fn level_init_dat(&mut self) -> Result<Option<Vec<u8>>> {
+        self.read_file("level-init.dat")
+    }
+
+    pub fn replay_dat(&mut self) -> Result<Option<Vec<u8>>> {
+        self.read_file("replay.dat")
+    }
+
+    fn path(&self, path: &str) -> String {
+        if self.root.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{path}", self.root)
+        }
+    }
+
+    /// Which preview image the save has, if any.
+    pub fn preview_format(&self) -> Option<PreviewFormat> {
+        [PreviewFormat::Jpeg, PreviewFormat::Png]
+            .into_iter()
+            .find(|format| self.source.contains(&self.path(format.file_name())))
+    }
+
+    /// The format and dimensions of the preview image, only its header is
+    /// read. Returns `None` if the save has no preview.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use factorio::{preview::PreviewFormat, save_file::SaveFile};
+    ///
+    /// let file = std::fs::File::open("test/test_0_13.zip").unwrap();
+    /// let preview = SaveFile::new(file).unwrap().preview().unwrap().unwrap();
+    /// assert_eq!(preview.format, PreviewFormat::Png);
+    /// assert_eq!((preview.width, preview.height), (640, 480));
+    /// ```
+    pub fn preview(&mut self) -> Result<Option<Preview>> {
+        let Some((format, mut file)) = self.preview_reader()? else {
+            return Ok(None);
+        };
+
+        let size = file.size();
+        let (width, height) = read_dimensions(format, &mut file)?;
+        Ok(Some(Preview {
+            format,
+            width,
+            height,
+            size,
+        }))
+    }
+
+    /// Streams the raw bytes of the preview image.
+    pub fn preview_reader(&mut self) -> Result<Option<(PreviewFormat, SourceFile<'_>)>> {
+        let Some(format) = self.preview_format() else {
+            return Ok(None);
+        };
+
+        let name = self.path(format.file_name());
+        Ok(self.source.open(&name)?.map(|file| (format, file)))
+    }
+
+    /// Writes a copy of the save as a zip archive to `writer` with the header
+    /// of the selected level data replaced by `header`, e.g. to change the mod
+    /// list. Returns `writer` when done.
+    ///
+    /// Only the level data entries whose contents change are recompressed,
+    /// every other entry of a zip archive is copied through unchanged. `header`
+    /// is written in the version of the save, as the rest of the level data is
+    /// not converted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use factorio::save_file::SaveFile;
+    ///
+    /// let file = std::fs::File::open("test/test_2_0_13_ext.zip").unwrap();
+    /// let mut save = SaveFile::new(file).unwrap();
+    ///
+    /// let mut header = save.header().unwrap();
+    /// header.mods.retain(|m| m.name != "RateCalculator");
+    ///
+    /// let patched = save
+    ///     .write_with_header(&header, std::io::Cursor::new(Vec::new()))
+    ///     .unwrap();
+    /// ```
+    pub fn write_with_header<W: Write + Seek>(
+        &mut self,
+        header: &SaveHeader,
+        writer: W,
+    ) -> Result<W> {
+        let mut level_data = Vec::new();
+        self.level_data()?.read_to_end(&mut level_data)?;
+
+        let mut rest = level_data.as_slice();
+        let version = get_save_header(&mut rest)?.factorio_version;
+        let mut patched = Vec::with_capacity(level_data.len());
+        header.write(&version, &mut patched)?;
+        patched.extend_from_slice(rest);
+
+        // the stored contents of every entry to replace or add
+        let mut replaced = BTreeMap::new();
+        let mut removed = Vec::new();
+        match &self.level_data {
+            LevelDataLayout::Single(name) => {
+                replaced.insert(name.clone(), patched);
+            }
+            LevelDataLayout::Chunked { chunks, metadata } => {
+                let prefix = &chunks[0][..chunks[0].len() - 1];
+                let chunk_size = LevelDataMetadata::CHUNK_SIZE as usize;
+                let old_chunks: Vec<_> = level_data.chunks(chunk_size).collect();
+                for (i, chunk) in patched.chunks(chunk_size).enumerate() {
+                    if old_chunks.get(i) == Some(&chunk) {
+                        continue;
+                    }
+
+                    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(chunk)?;
+                    replaced.insert(format!("{prefix}{i}"), encoder.finish()?);
+                }
+                removed.extend(
+                    chunks
+                        .iter()
+                        .skip(patched.len().div_ceil(chunk_size))
+                        .cloned(),
+                );
+
+                if metadata.is_some() && patched.len() != level_data.len() {
+                    replaced.insert(
+                        format!("{prefix}metadata"),
+                        (patched.len() as u64).to_le_bytes().to_vec(),
+                    );
+                }
+            }
+        }
+
+        self.write_replaced(replaced, &removed, writer)
+    }
+
+    /// Writes a copy of the save as a zip archive to `writer` with its
+    /// `script.dat` replaced by `script_data`, e.g. to reset the storage of a
+    /// broken mod. Returns `writer` when done.
+    ///
+    /// Every other entry of a zip archive is copied through unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use factorio::save_file::SaveFile;
+    ///
+    /// let file = std::fs::File::open("test/test_2_0_13_ext.zip").unwrap();
+    /// let mut save = SaveFile::new(file).unwrap();
+    ///
+    /// let mut script_data = save.script_data().unwrap().unwrap();
+    /// script_data.remove_mod("RateCalculator");
+    ///
+    /// let patched = save
+    ///     .write_with_script_data(&script_data, std::io::Cursor::new(Vec::new()))
+    ///     .unwrap();
+    /// ```
+    pub fn write_with_script_data<W: Write + Seek>(
+        &mut self,
+        script_data: &ScriptData,
+        writer: W,
+    ) -> Result<W> {
+        let mut buf = Vec::new();
+        script_data.write(&mut buf)?;
+        let replaced = BTreeMap::from([(self.path("script.dat"), buf)]);
+        self.write_replaced(replaced, &[], writer)
+    }
+
+    /// Copies the save to a zip archive written to `writer`, with the contents
+    /// of the files in `replaced` swapped out and the ones in `removed` left
+    /// out. Files of `replaced` that don't exist yet are appended without
+    /// compression.
+    fn write_replaced<W: Write + Seek>(
+        &mut self,
+        mut replaced: BTreeMap<String, Vec<u8>>,
+        removed: &[String],
+        writer: W,
+    ) -> Result<W> {
+        let mut zip = ZipWriter::new(writer);
+        let names: Vec<_> = self.source.file_names().map(str::to_string).collect();
+        for name in names.iter().filter(|name| !removed.contains(name)) {
+            let data = replaced.remove(name);
+            self.source.write_to_zip(name, data.as_deref(), &mut zip)?;
+        }
+
+        // e.g. chunks that didn't exist before, they are already zlib compressed
+        for (name, data) in replaced {
+            zip.start_file(
+                name,
+                SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+            )?;
+            zip.write_all(&data)?;
+        }
+
+        Ok(zip.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::{
+        script_data::{LuaValue, ScriptStorage},
+        test_util::{memory_source, write_zip},
+    };
+
+    fn open(path: &str) -> SaveFile<ZipArchive<File>> {
+        SaveFile::new(File::open(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_root() {
+        assert_eq!(open("test/test_0_13.zip").root(), "test_0_13_1");
+        assert_eq!(open("test/test_1_1.zip").root(), "test_1_1");
+        assert_eq!(open("test/test_1_1_14.zip").root(), "test1_1_14");
+        assert_eq!(open("test/test_2_0_13_ext.zip").root(), "test_2_0_13_ext");
+    }
+
+    #[test]
+    fn test_files() {
+        let mut save = open("test/test_1_1.zip");
+
+        let mut lua_files: Vec<_> = save.lua_files().collect();
+        lua_files.sort();
+        assert_eq!(lua_files, ["control.lua", "transportbeltmadness.lua"]);
+        assert!(save
+            .locale_files()
+            .any(|name| name == "campaign-locale/en/transport-belt-madness.cfg"));
+
+        assert!(save.control_lua().unwrap().is_some());
+        assert_eq!(
+            save.level_init_dat().unwrap().map(|buf| buf.len()),
+            Some(1170680)
+        );
+        assert_eq!(
+            save.script_init_dat().unwrap().map(|buf| buf.len()),
+            Some(1906)
+        );
+        assert_eq!(
+            save.info_json().unwrap().as_deref().map(str::trim),
+            Some("null")
+        );
+        assert_eq!(save.read_file("does-not-exist").unwrap(), None);
+    }
+
+    #[test]
+    fn test_files_sibling_folders() {
+        let save = SaveFile::new(write_zip(&[
+            ("save/level.dat", &[]),
+            ("save/control.lua", &[]),
+            ("save2/control.lua", &[]),
+            ("saveé/control.lua", &[]),
+        ]))
+        .unwrap();
+
+        let mut files: Vec<_> = save.files().collect();
+        files.sort();
+        assert_eq!(files, ["control.lua", "level.dat"]);
+    }
+
+    #[test]
+    fn test_replay() {
+        assert!(open("test/test_0_13.zip").replay_dat().unwrap().is_some());
+        assert!(open("test/test_2_0_13.zip").replay_dat().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut save = open("test/test_2_0_13.zip");
+        assert_eq!(
+            save.preview().unwrap(),
+            Some(Preview {
+                format: PreviewFormat::Jpeg,
+                width: 1920,
+                height: 1080,
+                size: 272290,
+            })
+        );
+
+        let (_, mut reader) = save.preview_reader().unwrap().unwrap();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf.len(), 272290);
+        assert_eq!(buf[..2], [0xff, 0xd8]);
+
+        let preview = open("test/test_0_16.zip").preview().unwrap().unwrap();
+        assert_eq!(preview.format, PreviewFormat::Png);
+    }
+
+    #[test]
+    fn test_no_preview() {
+        let mut save = SaveFile::new(write_zip(&[("save/level.dat", &[])])).unwrap();
+
+        assert_eq!(save.preview_format(), None);
+        assert_eq!(save.preview().unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_preview() {
+        let mut save = SaveFile::new(write_zip(&[
+            ("save/level.dat", &[]),
+            ("save/preview.png", &[0; 32]),
+        ]))
+        .unwrap();
+
+        assert!(matches!(
+            save.preview().unwrap_err(),
+            Error::InvalidPreview(PreviewFormat::Png)
+        ));
+    }
+
+    fn write_with_header(
+        save: &mut SaveFile<ZipArchive<File>>,
+        header: &SaveHeader,
+    ) -> SaveFile<ZipArchive<std::io::Cursor<Vec<u8>>>> {
+        let mut writer = save
+            .write_with_header(header, std::io::Cursor::new(Vec::new()))
+            .unwrap();
+        writer.set_position(0);
+        SaveFile::new(writer).unwrap()
+    }
+
+    fn read_level_data<S: SaveSource>(save: &mut SaveFile<S>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        save.level_data().unwrap().read_to_end(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_write_with_header_chunked() {
+        let mut save = open("test/test_2_0_13_ext.zip");
+        let mut header = save.header().unwrap();
+        let removed = header
+            .mods
+            .iter()
+            .find(|m| m.name == "RateCalculator")
+            .unwrap()
+            .clone();
+        header.mods.retain(|m| m.name != "RateCalculator");
+
+        let mut patched = write_with_header(&mut save, &header);
+        assert_eq!(patched.header().unwrap(), header);
+
+        let old = read_level_data(&mut save);
+        let new = read_level_data(&mut patched);
+        // name, three version numbers and the crc
+        let removed_len = 1 + removed.name.len() + 3 + 4;
+        assert_eq!(new.len(), old.len() - removed_len);
+        assert_eq!(old[old.len() - 1000..], new[new.len() - 1000..]);
+        assert!(matches!(
+            patched.level_data_layout(),
+            LevelDataLayout::Chunked {
+                metadata: Some(LevelDataMetadata { uncompressed_size }),
+                ..
+            } if *uncompressed_size == new.len() as u64
+        ));
+
+        assert_eq!(save.control_lua().unwrap(), patched.control_lua().unwrap());
+        assert_eq!(save.script_dat().unwrap(), patched.script_dat().unwrap());
+        assert_eq!(save.files().count(), patched.files().count());
+    }
+
+    #[test]
+    fn test_write_with_header_single() {
+        let mut save = open("test/test_1_1.zip");
+        let mut header = save.header().unwrap();
+        header.mods[0].version = [1, 1, 999];
+
+        let mut patched = write_with_header(&mut save, &header);
+        assert_eq!(patched.header().unwrap(), header);
+        assert_eq!(
+            read_level_data(&mut patched).len(),
+            read_level_data(&mut save).len() + 2
+        );
+    }
+
+    #[test]
+    fn test_write_with_header_memory() {
+        let mut save = SaveFile::from_source(memory_source("test/test_1_1_14.zip")).unwrap();
+        let mut header = save.header().unwrap();
+        let old_length = header.level_name.len();
+        header.level_name = "renamed".to_string();
+        let mut writer = save
+            .write_with_header(&header, std::io::Cursor::new(Vec::new()))
+            .unwrap();
+        writer.set_position(0);
+
+        let mut patched = SaveFile::new(writer).unwrap();
+        assert_eq!(patched.header().unwrap(), header);
+        assert_eq!(
+            read_level_data(&mut patched).len() + old_length,
+            read_level_data(&mut save).len() + header.level_name.len()
+        );
+        assert_eq!(patched.control_lua().unwrap(), save.control_lua().unwrap());
+        assert_eq!(patched.files().count(), save.files().count());
+    }
+
+    #[test]
+    fn test_write_with_script_data() {
+        let mut save = open("test/test_2_0_13_ext.zip");
+        let mut script_data = save.script_data().unwrap().unwrap();
+        assert!(script_data.remove_mod("RateCalculator").is_some());
+        let Some(ScriptStorage::Value { value, .. }) = script_data.get_mut("mod-AutoDeconstruct")
+        else {
+            panic!("expected a lua value");
+        };
+        *value = LuaValue::Table {
+            id: 0,
+            entries: Vec::new(),
+        };
+
+        let mut writer = save
+            .write_with_script_data(&script_data, std::io::Cursor::new(Vec::new()))
+            .unwrap();
+        writer.set_position(0);
+        let mut patched = SaveFile::new(writer).unwrap();
+        assert_eq!(patched.script_data().unwrap().unwrap(), script_data);
+
+        // everything else is copied as it was stored
+        assert_eq!(save.files().count(), patched.files().count());
+        let names: Vec<_> = save.source.file_names().map(str::to_string).collect();
+        fn read_raw<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Vec<u8> {
+            let mut buf = Vec::new();
+            let index = archive.index_for_name(name).unwrap();
+            archive
+                .by_index_raw(index)
+                .unwrap()
+                .read_to_end(&mut buf)
+                .unwrap();
+            buf
+        }
+        for name in names.iter().filter(|name| !name.ends_with("/script.dat")) {
+            assert_eq!(
+                read_raw(&mut save.source, name),
+                read_raw(&mut patched.source, name),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_level_init() {
+        let mut save = open("test/test_2_0_13_ext.zip");
+        let level = save.level_info().unwrap();
+
+        save.select_level_data(LevelDataFile::LevelInit).unwrap();
+        assert_eq!(save.level_data_file(), LevelDataFile::LevelInit);
+        assert_eq!(
+            save.level_data_layout(),
+            &LevelDataLayout::Single("test_2_0_13_ext/level-init.dat".to_string())
+        );
+        let init = save.level_info().unwrap();
+        assert_eq!(init.header.mods, level.header.mods);
+        assert_eq!(init.ticks_played, Some(0));
+        assert_eq!(read_level_data(&mut save).len(), 1415757);
+        save.scan_prototype_ids().unwrap();
+
+        save.select_level_data(LevelDataFile::Level).unwrap();
+        assert_eq!(save.level_info().unwrap(), level);
+    }
+
+    #[test]
+    fn test_missing_level_init() {
+        let mut save = SaveFile::new(write_zip(&[("save/level.dat", &[])])).unwrap();
+
+        let err = save
+            .select_level_data(LevelDataFile::LevelInit)
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingLevelInitDat));
+        assert_eq!(save.level_data_file(), LevelDataFile::Level);
+    }
+
+    #[test]
+    fn test_header() {
+        let mut save = open("test/test_2_0_13_ext.zip");
+        assert_eq!(
+            save.header().unwrap(),
+            crate::saves::get_save_header_by_path(File::open("test/test_2_0_13_ext.zip").unwrap())
+                .unwrap()
+        );
+    }
+}
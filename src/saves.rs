@@ -1,14 +1,36 @@
-use std::{
-    io,
-    io::{Read, Seek},
-};
+use std::io::{BufRead, BufReader, Read, Seek, Write};
 
 use flate2::read::ZlibDecoder;
+#[cfg(feature = "async")]
+use futures_lite::io::{AsyncRead, AsyncSeek};
 
-use crate::reader::{read_allow_non_admin_debug_options, read_array, read_build_version, read_large_blueprint_size, read_loaded_from, read_quality_version, read_string, FactorioReader};
+#[cfg(feature = "async")]
+use crate::async_reader::{parse_seekable, parse_stream};
+use crate::{
+    error::Error,
+    map_settings::{MapGenSettings, MapSettings},
+    preview::Preview,
+    property_tree::PropertyTree,
+    reader::{
+        read_allow_non_admin_debug_options, read_array, read_build_version, read_enum,
+        read_large_blueprint_size, read_loaded_from, read_mod_settings_crc, read_quality_version,
+        read_startup_mod_settings, read_string, CountingReader, FactorioReader,
+    },
+    save_file::SaveFile,
+    script_data::ScriptData,
+    source::IntoSaveSource,
+    writer::{
+        required, write_allow_non_admin_debug_options, write_array, write_build_version,
+        write_large_blueprint_size, write_loaded_from, write_mod_settings_crc,
+        write_quality_version, write_startup_mod_settings, write_string, FactorioOptimizedWriter,
+        FactorioWriter,
+    },
+    zip_stream::{LocalEntry, ZipStream},
+    Result,
+};
 
 #[repr(u8)]
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Difficulty {
     Easy    = 0x0,
     Normal  = 0x1,
@@ -16,37 +38,37 @@ pub enum Difficulty {
     Nothing = 0x3,
 }
 
-impl From<u8> for Difficulty {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for Difficulty {
+    type Error = u8;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
         match value {
-            0 => Difficulty::Easy,
-            1 => Difficulty::Normal,
-            2 => Difficulty::Hard,
-            3 => Difficulty::Nothing,
-            _ => {
-                panic!("invalid value: {value}")
-            }
+            0 => Ok(Difficulty::Easy),
+            1 => Ok(Difficulty::Normal),
+            2 => Ok(Difficulty::Hard),
+            3 => Ok(Difficulty::Nothing),
+            _ => Err(value),
         }
     }
 }
 
 #[repr(u8)]
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum AllowedCommands {
-    True,
-    False,
-    AdminsOnly,
+    True       = 0x1,
+    False      = 0x2,
+    AdminsOnly = 0x3,
 }
 
-impl From<u8> for AllowedCommands {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for AllowedCommands {
+    type Error = u8;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
         match value {
-            1 => AllowedCommands::True,
-            2 => AllowedCommands::False,
-            3 => AllowedCommands::AdminsOnly,
-            _ => {
-                panic!("invalid value: {value}")
-            }
+            1 => Ok(AllowedCommands::True),
+            2 => Ok(AllowedCommands::False),
+            3 => Ok(AllowedCommands::AdminsOnly),
+            _ => Err(value),
         }
     }
 }
@@ -60,7 +82,27 @@ impl From<[u16; 4]> for FactorioVersion {
     }
 }
 
-#[derive(PartialEq, Debug)]
+impl FactorioReader for FactorioVersion {
+    fn read(version: &FactorioVersion, reader: &mut impl Read) -> Result<Self> {
+        Ok(Self([
+            u16::read(version, reader)?,
+            u16::read(version, reader)?,
+            u16::read(version, reader)?,
+            u16::read(version, reader)?,
+        ]))
+    }
+}
+
+impl FactorioWriter for FactorioVersion {
+    fn write(&self, version: &FactorioVersion, writer: &mut impl Write) -> Result<()> {
+        for part in self.0 {
+            part.write(version, writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub struct SaveHeader {
     pub factorio_version: FactorioVersion,
     pub quality_version: Option<u8>,
@@ -79,15 +121,12 @@ pub struct SaveHeader {
     pub loaded_from_build: u32,
     pub allowed_commands: AllowedCommands,
     pub lange_blueprint_size: Option<u32>,
-    pub mods: Vec<Mod>, /* called "active-mods" in factorio */
-
-                        /* the following is untracked and just a first glance in 1.1 factorio
-                         * pub ModSettingsChecksum: u32,
-                         * pub ModSettings: SomeMap,
-                         * pub MapData: ??, */
+    pub mods: Vec<Mod>,                // called "active-mods" in factorio
+    pub mod_settings_crc: Option<u32>, // since 0.16
+    pub startup_mod_settings: Option<PropertyTree>, // since 0.16, setting name -> { "value": ... }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Mod {
     pub name: String,
     pub version: [u16; 3],
@@ -95,11 +134,11 @@ pub struct Mod {
 }
 
 impl FactorioReader for Mod {
-    fn read(factorio_version: &FactorioVersion, reader: &mut impl Read) -> io::Result<Self> {
+    fn read(factorio_version: &FactorioVersion, reader: &mut impl Read) -> Result<Self> {
         let name = if factorio_version >= &[0, 14, 0, 0].into() {
-            read_string(factorio_version, reader, true)?
+            read_string("mod name", factorio_version, reader, true)?
         } else {
-            read_string(factorio_version, reader, false)?
+            read_string("mod name", factorio_version, reader, false)?
         };
 
         let version = if factorio_version >= &[0, 14, 14, 0].into() {
@@ -126,8 +165,76 @@ impl FactorioReader for Mod {
     }
 }
 
+impl FactorioWriter for Mod {
+    fn write(&self, factorio_version: &FactorioVersion, writer: &mut impl Write) -> Result<()> {
+        let force_optimized = factorio_version >= &[0, 14, 0, 0].into();
+        write_string(&self.name, factorio_version, writer, force_optimized)?;
+
+        for part in self.version {
+            if factorio_version >= &[0, 14, 14, 0].into() {
+                part.write_optimized(factorio_version, writer)?;
+            } else {
+                part.write(factorio_version, writer)?;
+            }
+        }
+
+        if factorio_version >= &[0, 15, 0, 91].into() {
+            required("mod crc", self.crc, factorio_version)?.write(factorio_version, writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl SaveHeader {
+    /// Serializes the header the way Factorio `version` does, the inverse of
+    /// [`get_save_header`]. `version` is written in place of
+    /// `self.factorio_version`.
+    ///
+    /// Fields which don't exist in that version are skipped, fails with
+    /// [`Error::MissingField`] if an optional field which does exist is
+    /// `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use factorio::saves::{get_save_header, get_save_header_by_path};
+    ///
+    /// let file = std::fs::File::open("test/test_1_1.zip").unwrap();
+    /// let mut header = get_save_header_by_path(file).unwrap();
+    /// header.level_name = "patched".to_string();
+    ///
+    /// let mut buf = Vec::new();
+    /// header.write(&header.factorio_version, &mut buf).unwrap();
+    /// assert_eq!(get_save_header(&mut buf.as_slice()).unwrap(), header);
+    /// ```
+    pub fn write(&self, version: &FactorioVersion, writer: &mut impl Write) -> Result<()> {
+        version.write(version, writer)?;
+        write_quality_version(self.quality_version, version, writer)?;
+        write_string(&self.campaign_name, version, writer, false)?;
+        write_string(&self.level_name, version, writer, false)?;
+        write_string(&self.base_mod_name, version, writer, false)?;
+        (self.difficulty as u8).write(version, writer)?;
+        (self.finished as u8).write(version, writer)?;
+        (self.player_won as u8).write(version, writer)?;
+        write_string(&self.next_level, version, writer, false)?;
+        (self.can_continue as u8).write(version, writer)?;
+        (self.finished_but_continuing as u8).write(version, writer)?;
+        (self.saving_replay as u8).write(version, writer)?;
+        write_allow_non_admin_debug_options(self.allow_non_admin_debug_options, version, writer)?;
+        write_loaded_from(&self.loaded_from, version, writer)?;
+        write_build_version(self.loaded_from_build, version, writer)?;
+        (self.allowed_commands as u8).write(version, writer)?;
+        write_large_blueprint_size(self.lange_blueprint_size, version, writer)?;
+        write_array(&self.mods, version, writer)?;
+        write_mod_settings_crc(self.mod_settings_crc, version, writer)?;
+        write_startup_mod_settings(self.startup_mod_settings.as_ref(), version, writer)
+    }
+}
+
 /// Get the header of a savefile by a reader to a `level.dat` file.
-/// In Factorio >= 1.1.14 that has to be the zlib decompressed `level.dat0` file
+/// In Factorio >= 1.1.14 that has to be the zlib decompressed `level.dat0`
+/// file, [`LevelDataReader`](crate::level_data::LevelDataReader) takes care of
+/// that for a whole save archive.
 ///
 /// # Arguments
 ///
@@ -135,6 +242,10 @@ impl FactorioReader for Mod {
 ///
 /// returns: Result<SaveHeader, Error>
 ///
+/// Fails with [`Error::UnsupportedVersion`] for saves older than 0.13, with
+/// [`Error::UnexpectedEof`] if the input is truncated and with
+/// [`Error::InvalidEnum`] if an enum field holds an unknown value.
+///
 /// # Examples
 ///
 /// ```
@@ -156,71 +267,299 @@ impl FactorioReader for Mod {
 /// let mut decoder = flate2::read::ZlibDecoder::new(level_init);
 /// let header = get_save_header(&mut decoder).unwrap();
 /// ```
-pub fn get_save_header(reader: &mut impl Read) -> io::Result<SaveHeader> {
-    let save_version: FactorioVersion = [
-        u16::read(&[0, 0, 0, 0].into(), reader)?,
-        u16::read(&[0, 0, 0, 0].into(), reader)?,
-        u16::read(&[0, 0, 0, 0].into(), reader)?,
-        u16::read(&[0, 0, 0, 0].into(), reader)?,
-    ]
-    .into();
+pub fn get_save_header(reader: &mut impl Read) -> Result<SaveHeader> {
+    let reader = &mut CountingReader::new(reader);
+    let save_version = FactorioVersion::read(&[0, 0, 0, 0].into(), reader)?;
+
+    if save_version < [0, 13, 0, 0].into() {
+        return Err(Error::UnsupportedVersion(save_version));
+    }
 
     let res = SaveHeader {
         factorio_version: save_version,
         quality_version: read_quality_version(&save_version, reader)?,
-        campaign_name: read_string(&save_version, reader, false)?,
-        level_name: read_string(&save_version, reader, false)?,
-        base_mod_name: read_string(&save_version, reader, false)?,
-        difficulty: u8::read(&save_version, reader)?.into(),
+        campaign_name: read_string("campaign name", &save_version, reader, false)?,
+        level_name: read_string("level name", &save_version, reader, false)?,
+        base_mod_name: read_string("base mod name", &save_version, reader, false)?,
+        difficulty: read_enum("difficulty", &save_version, reader)?,
         finished: u8::read(&save_version, reader)? != 0,
         player_won: u8::read(&save_version, reader)? != 0,
-        next_level: read_string(&save_version, reader, false)?,
+        next_level: read_string("next level", &save_version, reader, false)?,
         can_continue: u8::read(&save_version, reader)? != 0,
         finished_but_continuing: u8::read(&save_version, reader)? != 0,
         saving_replay: u8::read(&save_version, reader)? != 0,
         allow_non_admin_debug_options: read_allow_non_admin_debug_options(&save_version, reader)?,
         loaded_from: read_loaded_from(&save_version, reader)?,
         loaded_from_build: read_build_version(&save_version, reader)?,
-        allowed_commands: u8::read(&save_version, reader)?.into(),
+        allowed_commands: read_enum("allowed commands", &save_version, reader)?,
         lange_blueprint_size: read_large_blueprint_size(&save_version, reader)?,
         mods: read_array::<Mod>(&save_version, reader)?,
+        mod_settings_crc: read_mod_settings_crc(&save_version, reader)?,
+        startup_mod_settings: read_startup_mod_settings(&save_version, reader)?,
     };
 
     Ok(res)
 }
 
-pub fn get_save_header_by_path(reader: impl Read + Seek) -> io::Result<SaveHeader> {
-    let mut archive = zip::ZipArchive::new(reader)?;
-
-    let dat_info = archive
-        .file_names()
-        .enumerate()
-        .find_map(move |(i, filename)| {
-            if filename.ends_with("level.dat") {
-                return Some((i, false));
-            } else if filename.ends_with("level.dat0") {
-                return Some((i, true));
-            }
-            None
-        })
-        .ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+/// The parts of the level data following the header that we can read.
+///
+/// The game speed isn't included, we haven't found where it is stored.
+/// Whether a save is an autosave, or who saved it, isn't stored here either,
+/// autosaves are only told apart by their file name (`_autosave1.zip`).
+#[derive(PartialEq, Debug, Clone)]
+pub struct LevelInfo {
+    pub header: SaveHeader,
+    pub tick: u64,                                // `game.tick`
+    pub ticks_played: Option<u64>,                // since 0.17, `game.ticks_played`
+    pub map_gen_settings: Option<MapGenSettings>, // since 0.17
+}
+
+/// Reads the header and everything after it up to the map settings from a
+/// reader to the level data, see [`get_save_header`].
+///
+/// Before 0.17 only the tick is read.
+///
+/// # Examples
+///
+/// ```
+/// use factorio::{level_data::LevelDataReader, saves::get_level_info};
+///
+/// let file = std::fs::File::open("test/test_2_0_13.zip").unwrap();
+/// let mut archive = zip::ZipArchive::new(file).unwrap();
+/// let info = get_level_info(&mut LevelDataReader::new(&mut archive).unwrap()).unwrap();
+/// assert_eq!(info.tick, 598);
+/// let seed = info.map_gen_settings.unwrap().seed;
+/// ```
+pub fn get_level_info(reader: &mut impl Read) -> Result<LevelInfo> {
+    let reader = &mut CountingReader::new(reader);
+    let header = get_save_header(reader)?;
+    let version = header.factorio_version;
+
+    if version < [0, 17, 0, 0].into() {
+        // bytes which are always zero in the saves we have, if they aren't
+        // the tick may be stored somewhere else
+        let offset = reader.position();
+        let unknown = if version < [0, 14, 0, 0].into() {
+            u32::read(&version, reader)? as u64
+        } else if version < [0, 16, 0, 0].into() {
+            u64::read(&version, reader)?
+        } else {
+            0
+        };
+        if unknown != 0 {
+            return Err(Error::Unsupported {
+                field: "tick",
+                offset,
+            });
+        }
+
+        return Ok(LevelInfo {
+            header,
+            tick: u32::read(&version, reader)? as u64,
+            ticks_played: None,
+            map_gen_settings: None,
+        });
+    }
+
+    let (tick, ticks_played) = read_ticks(&version, reader)?;
+    Ok(LevelInfo {
+        header,
+        tick,
+        ticks_played: Some(ticks_played),
+        map_gen_settings: Some(MapGenSettings::read(&version, reader)?),
+    })
+}
+
+/// Reads the runtime map settings following the [`LevelInfo`] from a reader to
+/// the level data.
+///
+/// Only supports 0.17 saves and fails with [`Error::Unsupported`] for all
+/// others. Later versions only store the settings that differ from the game's
+/// defaults, in a layout that has not been mapped out yet. The pollution
+/// settings are not read either, 0.17 saves whose bytes in front of the
+/// steering settings differ from the default ones fail the same way.
+///
+/// # Examples
+///
+/// ```
+/// use factorio::{level_data::LevelDataReader, saves::get_map_settings};
+///
+/// let file = std::fs::File::open("test/test_0_17.zip").unwrap();
+/// let mut archive = zip::ZipArchive::new(file).unwrap();
+/// let map_settings = get_map_settings(&mut LevelDataReader::new(&mut archive).unwrap()).unwrap();
+/// assert_eq!(map_settings.enemy_evolution.enabled, Some(true));
+/// ```
+pub fn get_map_settings(reader: &mut impl Read) -> Result<MapSettings> {
+    let reader = &mut CountingReader::new(reader);
+    let version = get_level_info(reader)?.header.factorio_version;
+    if version < [0, 17, 0, 0].into() || version >= [0, 18, 0, 0].into() {
+        return Err(Error::Unsupported {
+            field: "map settings",
+            offset: reader.position(),
+        });
+    }
+    MapSettings::read(&version, reader)
+}
+
+/// Reads `game.tick` and `game.ticks_played`, which are followed by a third
+/// counter that is equal to `ticks_played` in every save we have.
+fn read_ticks<R: Read>(
+    version: &FactorioVersion,
+    reader: &mut CountingReader<R>,
+) -> Result<(u64, u64)> {
+    if version < &[2, 0, 0, 0].into() {
+        let tick = u32::read(version, reader)?;
+        let ticks_played = u32::read(version, reader)?;
+        u32::read(version, reader)?;
+        return Ok((tick as u64, ticks_played as u64));
+    }
+
+    // the victory and defeat screens: title, three empty parts and an image.
+    // Every save we have uses the built-in screens, other contents fail
+    // rather than being read with a layout we can't check.
+    for _ in 0..2 {
+        let offset = reader.position();
+        let kind = u8::read(version, reader)?;
+        read_string("ending title", version, reader, false)?;
+        let parts = u32::read(version, reader)?;
+        if kind != 1 || parts != 0 {
+            return Err(Error::Unsupported {
+                field: "ending screen",
+                offset,
+            });
+        }
+        read_string("ending image", version, reader, false)?;
+    }
+    // differs between saves, meaning unknown
+    u8::read(version, reader)?;
+
+    let tick = u64::read(version, reader)?;
+    let ticks_played = u64::read(version, reader)?;
+    u64::read(version, reader)?;
+    Ok((tick, ticks_played))
+}
+
+/// Shorthand for [`SaveFile::header`] if nothing else of the save is needed.
+///
+/// Takes a reader to a save zip or any other [`IntoSaveSource`], e.g. a
+/// [`DirectorySource`](crate::source::DirectorySource) for an unpacked save.
+pub fn get_save_header_by_path(source: impl IntoSaveSource) -> Result<SaveHeader> {
+    SaveFile::from_source(source.into_save_source()?)?.header()
+}
 
-    let mut file = archive.by_index(dat_info.0)?;
+/// Reads the header of a save zip from a stream that can't seek, e.g. an
+/// upload that is still in progress.
+///
+/// The entries of the archive are walked in order until `level.dat` or
+/// `level.dat0` is reached, so only the data stored before it is read. Fails
+/// with [`Error::MissingLevelDat`] if there is none.
+///
+/// # Examples
+///
+/// ```
+/// use factorio::saves::{get_save_header_by_path, get_save_header_from_stream};
+///
+/// let data = std::fs::read("test/test_2_0_13_ext.zip").unwrap();
+/// let header = get_save_header_from_stream(data.as_slice()).unwrap();
+/// assert_eq!(
+///     header,
+///     get_save_header_by_path(std::io::Cursor::new(data)).unwrap()
+/// );
+/// ```
+pub fn get_save_header_from_stream(reader: impl Read) -> Result<SaveHeader> {
+    let mut stream = ZipStream::new(BufReader::new(reader));
+    while let Some(entry) = stream.next_entry()? {
+        if is_level_dat(&entry.name) {
+            return read_level_dat_header(&mut stream, &entry);
+        }
+        stream.skip(&entry)?;
+    }
+    Err(Error::MissingLevelDat)
+}
 
-    // if 1 is true, it is zlib compressed
-    if dat_info.1 {
-        let mut file = ZlibDecoder::new(file);
-        get_save_header(&mut file)
+fn is_level_dat(name: &str) -> bool {
+    matches!(name.rsplit('/').next(), Some("level.dat" | "level.dat0"))
+}
+
+/// `level.dat0` is the first zlib compressed chunk of the level data.
+fn read_level_dat_header<R: BufRead>(
+    stream: &mut ZipStream<R>,
+    entry: &LocalEntry,
+) -> Result<SaveHeader> {
+    let mut contents = stream.contents(entry)?;
+    if entry.name.ends_with('0') {
+        get_save_header(&mut ZlibDecoder::new(contents))
     } else {
-        get_save_header(&mut file)
+        get_save_header(&mut contents)
     }
 }
 
+/// Async version of [`get_save_header`], with the `async` feature.
+///
+/// The header is read by the same code as in [`get_save_header`], `reader`
+/// may be read past its end. Readers of tokio can be adapted with
+/// `tokio_util::compat`.
+#[cfg(feature = "async")]
+pub async fn get_save_header_async(reader: &mut (impl AsyncRead + Unpin)) -> Result<SaveHeader> {
+    parse_stream(reader, get_save_header).await
+}
+
+/// Async version of [`get_save_header_by_path`] for save zips, with the
+/// `async` feature.
+///
+/// The archive is read by [`get_save_header_by_path`] itself, only the parts
+/// of it that are needed for the header are fetched from `reader`. Unpacked
+/// saves are plain files on disk or in memory, they are read with
+/// [`get_save_header_by_path`] directly.
+///
+/// # Examples
+///
+/// ```
+/// # futures_lite::future::block_on(async {
+/// use factorio::saves::get_save_header_by_path_async;
+///
+/// let data = std::fs::read("test/test_1_1.zip").unwrap();
+/// let header = get_save_header_by_path_async(futures_lite::io::Cursor::new(data))
+///     .await
+///     .unwrap();
+/// assert_eq!(header.level_name, "level-01");
+/// # });
+/// ```
+#[cfg(feature = "async")]
+pub async fn get_save_header_by_path_async(
+    mut reader: impl AsyncRead + AsyncSeek + Unpin,
+) -> Result<SaveHeader> {
+    parse_seekable(&mut reader, |fetched| get_save_header_by_path(fetched)).await
+}
+
+/// Shorthand for [`SaveFile::write_with_script_data`], writes a copy of the
+/// save with `script_data` as its `script.dat` to `writer`.
+pub fn write_script_data_by_path<W: Write + Seek>(
+    source: impl IntoSaveSource,
+    script_data: &ScriptData,
+    writer: W,
+) -> Result<W> {
+    SaveFile::from_source(source.into_save_source()?)?.write_with_script_data(script_data, writer)
+}
+
+/// Shorthand for [`SaveFile::preview`], returns `None` if the save has no
+/// preview image.
+pub fn get_save_preview_by_path(source: impl IntoSaveSource) -> Result<Option<Preview>> {
+    SaveFile::from_source(source.into_save_source()?)?.preview()
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs::File, path::Path};
 
     use super::*;
+    use crate::{
+        map_settings::{
+            AutoplaceControl, AutoplaceSettings, CliffSettings, EnemyEvolutionSettings, MapPosition,
+        },
+        property_tree::PropertyTreeValue,
+        test_util::{test_saves, write_zip},
+    };
 
     #[test]
     fn test_2_0_13_ext() {
@@ -279,6 +618,8 @@ mod tests {
                     crc: Some(542481865),
                 },
             ],
+            mod_settings_crc: Some(0),
+            startup_mod_settings: Some(PropertyTreeValue::Dictionary(vec![]).into()),
         };
 
         let path = Path::new("test/test_2_0_13_ext.zip");
@@ -286,7 +627,7 @@ mod tests {
         let test = get_save_header_by_path(file).unwrap();
         assert_eq!(header, test);
     }
-    
+
     #[test]
     fn test_2_0_13() {
         let header = SaveHeader {
@@ -329,6 +670,8 @@ mod tests {
                     crc: Some(2191928673),
                 },
             ],
+            mod_settings_crc: Some(0),
+            startup_mod_settings: Some(PropertyTreeValue::Dictionary(vec![]).into()),
         };
 
         let path = Path::new("test/test_2_0_13.zip");
@@ -374,6 +717,8 @@ mod tests {
                     crc: Some(442638023),
                 },
             ],
+            mod_settings_crc: Some(0),
+            startup_mod_settings: Some(PropertyTreeValue::Dictionary(vec![]).into()),
         };
 
         let path = Path::new("test/test_1_1_14.zip");
@@ -419,6 +764,8 @@ mod tests {
                     crc: Some(442638023),
                 },
             ],
+            mod_settings_crc: Some(0),
+            startup_mod_settings: Some(PropertyTreeValue::Dictionary(vec![]).into()),
         };
 
         let path = Path::new("test/test_1_1.zip");
@@ -465,6 +812,8 @@ mod tests {
                     crc: Some(442638023),
                 },
             ],
+            mod_settings_crc: Some(0),
+            startup_mod_settings: Some(PropertyTreeValue::Dictionary(vec![]).into()),
         };
 
         let path = Path::new("test/test_0_18.zip");
@@ -506,6 +855,36 @@ mod tests {
                     crc: Some(819815259),
                 },
             ],
+            mod_settings_crc: Some(3030239893),
+            startup_mod_settings: Some(
+                PropertyTreeValue::Dictionary(vec![
+                    (
+                        "Warehousing-icon-scaling".to_string(),
+                        PropertyTreeValue::Dictionary(vec![(
+                            "value".to_string(),
+                            PropertyTreeValue::Bool(true).into(),
+                        )])
+                        .into(),
+                    ),
+                    (
+                        "Warehousing-copy-logistic-system".to_string(),
+                        PropertyTreeValue::Dictionary(vec![(
+                            "value".to_string(),
+                            PropertyTreeValue::Bool(false).into(),
+                        )])
+                        .into(),
+                    ),
+                    (
+                        "Warehousing-sixteen-mode".to_string(),
+                        PropertyTreeValue::Dictionary(vec![(
+                            "value".to_string(),
+                            PropertyTreeValue::Bool(false).into(),
+                        )])
+                        .into(),
+                    ),
+                ])
+                .into(),
+            ),
         };
 
         let path = Path::new("test/test_0_17.zip");
@@ -547,6 +926,8 @@ mod tests {
                     crc: Some(3323233190),
                 },
             ],
+            mod_settings_crc: Some(0),
+            startup_mod_settings: Some(PropertyTreeValue::None.into()),
         };
 
         let path = Path::new("test/test_0_16.zip");
@@ -588,6 +969,8 @@ mod tests {
                     crc: Some(1503927233),
                 },
             ],
+            mod_settings_crc: None,
+            startup_mod_settings: None,
         };
 
         let path = Path::new("test/test_0_15.zip");
@@ -629,6 +1012,8 @@ mod tests {
                     crc: None,
                 },
             ],
+            mod_settings_crc: None,
+            startup_mod_settings: None,
         };
 
         let path = Path::new("test/test_0_14.zip");
@@ -670,6 +1055,8 @@ mod tests {
                     crc: None,
                 },
             ],
+            mod_settings_crc: None,
+            startup_mod_settings: None,
         };
 
         let path = Path::new("test/test_0_13.zip");
@@ -678,4 +1065,402 @@ mod tests {
 
         assert_eq!(header, test);
     }
+
+    fn round_trip(path: &str) {
+        let mut save = SaveFile::new(File::open(path).unwrap()).unwrap();
+        let mut level_data = Vec::new();
+        save.level_data()
+            .unwrap()
+            .read_to_end(&mut level_data)
+            .unwrap();
+
+        let header = get_save_header(&mut level_data.as_slice()).unwrap();
+        let mut written = Vec::new();
+        header
+            .write(&header.factorio_version, &mut written)
+            .unwrap();
+        assert_eq!(written, level_data[..written.len()], "{path}");
+    }
+
+    #[test]
+    fn test_write() {
+        for path in test_saves() {
+            round_trip(path.to_str().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_write_missing_field() {
+        let header = get_save_header_by_path(File::open("test/test_0_13.zip").unwrap()).unwrap();
+        let err = header
+            .write(&[1, 1, 0, 0].into(), &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MissingField {
+                field: "quality version",
+                ..
+            }
+        ));
+    }
+
+    fn level_info(path: &str) -> LevelInfo {
+        SaveFile::new(File::open(path).unwrap())
+            .unwrap()
+            .level_info()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_ticks() {
+        let ticks = |path| {
+            let info = level_info(path);
+            (info.tick, info.ticks_played)
+        };
+        assert_eq!(ticks("test/test_0_13.zip"), (54, None));
+        assert_eq!(ticks("test/test_0_14.zip"), (44, None));
+        assert_eq!(ticks("test/test_0_15.zip"), (29, None));
+        assert_eq!(ticks("test/test_0_16.zip"), (52, None));
+        assert_eq!(ticks("test/test_0_17.zip"), (113, Some(113)));
+        assert_eq!(ticks("test/test_0_18.zip"), (233, Some(28)));
+        assert_eq!(ticks("test/test_1_1.zip"), (321, Some(98)));
+        assert_eq!(ticks("test/test_1_1_14.zip"), (267, Some(44)));
+        assert_eq!(ticks("test/test_2_0_13.zip"), (598, Some(360)));
+        assert_eq!(ticks("test/test_2_0_13_ext.zip"), (376, Some(138)));
+    }
+
+    #[test]
+    fn test_unknown_tick_prefix() {
+        for path in ["test/test_0_13.zip", "test/test_0_15.zip"] {
+            let mut save = SaveFile::new(File::open(path).unwrap()).unwrap();
+            let mut data = Vec::new();
+            save.level_data().unwrap().read_to_end(&mut data).unwrap();
+            let mut rest = data.as_slice();
+            get_save_header(&mut rest).unwrap();
+            let offset = data.len() - rest.len();
+
+            data[offset] = 1;
+            let err = get_level_info(&mut data.as_slice()).unwrap_err();
+            assert!(
+                matches!(err, Error::Unsupported { field: "tick", offset: o } if o == offset as u64),
+                "{path}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_map_gen_settings() {
+        let controls = |names: &[&str]| -> Vec<AutoplaceControl> {
+            names
+                .iter()
+                .map(|name| AutoplaceControl {
+                    name: name.to_string(),
+                    frequency: 1.0,
+                    size: 1.0,
+                    richness: 1.0,
+                })
+                .collect()
+        };
+        let ores = [
+            "coal",
+            "copper-ore",
+            "crude-oil",
+            "enemy-base",
+            "iron-ore",
+            "stone",
+        ];
+
+        let mut expected = MapGenSettings {
+            terrain_segmentation: Some(1.0),
+            water: Some(1.0),
+            autoplace_controls: controls(&ores),
+            autoplace_settings: Vec::new(),
+            default_enable_all_autoplace_controls: true,
+            seed: 0,
+            width: 2000000,
+            height: 2000000,
+            area_to_generate_at_start: (
+                MapPosition {
+                    x: -448.0,
+                    y: -448.0,
+                },
+                MapPosition { x: 448.0, y: 448.0 },
+            ),
+            starting_area: 1.0,
+            peaceful_mode: false,
+            no_enemies_mode: None,
+            starting_points: vec![MapPosition { x: 0.0, y: 0.0 }],
+            property_expression_names: vec![(
+                "elevation".to_string(),
+                "0_16-elevation".to_string(),
+            )],
+            cliff_settings: CliffSettings {
+                name: "cliff".to_string(),
+                control: None,
+                cliff_elevation_0: 10.0,
+                cliff_elevation_interval: 40.0,
+                richness: 1.0,
+                cliff_smoothing: None,
+            },
+        };
+        for path in [
+            "test/test_0_17.zip",
+            "test/test_0_18.zip",
+            "test/test_1_1.zip",
+            "test/test_1_1_14.zip",
+        ] {
+            assert_eq!(
+                level_info(path).map_gen_settings.as_ref(),
+                Some(&expected),
+                "{path}"
+            );
+        }
+
+        expected.terrain_segmentation = None;
+        expected.water = None;
+        expected.autoplace_controls = controls(&[&ores[..], &["water"]].concat());
+        expected.no_enemies_mode = Some(false);
+        expected.cliff_settings.control = Some(String::new());
+        expected.cliff_settings.cliff_smoothing = Some(1.0);
+        for path in ["test/test_2_0_13.zip", "test/test_2_0_13_ext.zip"] {
+            assert_eq!(
+                level_info(path).map_gen_settings.as_ref(),
+                Some(&expected),
+                "{path}"
+            );
+        }
+
+        assert_eq!(level_info("test/test_0_16.zip").map_gen_settings, None);
+    }
+
+    #[test]
+    fn test_map_settings() {
+        let read_map_settings = |path| {
+            SaveFile::new(File::open(path).unwrap())
+                .unwrap()
+                .map_settings()
+        };
+        let map_settings = read_map_settings("test/test_0_17.zip").unwrap();
+        assert_eq!(
+            map_settings.enemy_evolution,
+            EnemyEvolutionSettings {
+                enabled: Some(true),
+                time_factor: Some(0.000004),
+                destroy_factor: Some(0.002),
+                pollution_factor: Some(0.000015),
+            }
+        );
+        assert_eq!(
+            map_settings.enemy_expansion.max_expansion_cooldown,
+            Some(216000)
+        );
+        assert_eq!(map_settings.unit_group.max_unit_group_size, Some(200));
+        assert_eq!(
+            map_settings
+                .path_finder
+                .start_to_goal_cost_multiplier_to_terminate_path_find,
+            Some(500.0)
+        );
+
+        for path in ["test/test_0_16.zip", "test/test_1_1.zip"] {
+            let err = read_map_settings(path).unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    Error::Unsupported {
+                        field: "map settings",
+                        ..
+                    }
+                ),
+                "{path}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_autoplace_settings() {
+        let string = |data: &mut Vec<u8>, value: &str| {
+            data.push(value.len() as u8);
+            data.extend(value.as_bytes());
+        };
+        // 1.1 map gen settings with no autoplace controls and tree overrides
+        let mut data = Vec::new();
+        data.extend([1.0f32, 1.0].iter().flat_map(|value| value.to_le_bytes()));
+        data.extend([0, 1]);
+        string(&mut data, "entity");
+        data.extend([1, 1]);
+        string(&mut data, "trees");
+        data.extend(
+            [2.0f32, 1.0, 0.5]
+                .iter()
+                .flat_map(|value| value.to_le_bytes()),
+        );
+        data.extend([1, 0, 0, 0, 0]); // default_enable_all_autoplace_controls, seed
+        data.extend([0; 8]); // width and height
+        data.extend([0; 8]); // area_to_generate_at_start as two deltas
+        data.extend([0; 4]);
+        data.extend(1.0f32.to_le_bytes());
+        data.extend([0, 0, 0]); // peaceful mode, starting points, property expressions
+        string(&mut data, "cliff");
+        data.extend(
+            [10.0f32, 40.0, 1.0]
+                .iter()
+                .flat_map(|value| value.to_le_bytes()),
+        );
+
+        let version = [1, 1, 0, 0].into();
+        let reader = &mut CountingReader::new(data.as_slice());
+        let settings = MapGenSettings::read(&version, reader).unwrap();
+        assert_eq!(
+            settings.autoplace_settings,
+            vec![AutoplaceSettings {
+                prototype_type: "entity".to_string(),
+                treat_missing_as_default: true,
+                settings: vec![AutoplaceControl {
+                    name: "trees".to_string(),
+                    frequency: 2.0,
+                    size: 1.0,
+                    richness: 0.5,
+                }],
+            }]
+        );
+        assert_eq!(settings.cliff_settings.name, "cliff");
+        assert_eq!(reader.position(), data.len() as u64);
+    }
+
+    #[test]
+    fn test_unknown_ending_screen() {
+        let mut data = vec![2, 1, b'x'];
+        data.extend([0; 4]);
+        let reader = &mut CountingReader::new(data.as_slice());
+        let err = read_ticks(&[2, 0, 13, 0].into(), reader).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Unsupported {
+                field: "ending screen",
+                offset: 0,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_unknown_pollution_settings() {
+        let data = [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let reader = &mut CountingReader::new(data.as_slice());
+        let err = MapSettings::read(&[0, 17, 79, 0].into(), reader).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Unsupported {
+                field: "pollution settings",
+                offset: 0,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_invalid_difficulty() {
+        // 1.1.6.4, quality version, three empty strings and then an unknown
+        // difficulty
+        let data = [1, 0, 1, 0, 6, 0, 4, 0, 0, 0, 0, 0, 7];
+        let err = get_save_header(&mut data.as_slice()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidEnum {
+                field: "difficulty",
+                value: 7,
+                offset: 12
+            }
+        ));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = [1, 0, 1, 0, 6, 0, 4, 0, 0, 4, b'b', b'a'];
+        let err = get_save_header(&mut data.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_corrupt_length() {
+        // a 1.1 header announcing u32::MAX mods
+        let mut data = vec![
+            1, 0, 1, 0, 6, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 6, 0, 0, 1,
+        ];
+        data.extend([0xFF; 5]);
+        let err = get_save_header(&mut data.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedEof));
+
+        // and a campaign name of u32::MAX bytes
+        let data = [
+            1, 0, 1, 0, 6, 0, 4, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, b'a',
+        ];
+        let err = get_save_header(&mut data.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let data = [0, 0, 12, 0, 35, 0, 0, 0];
+        let err = get_save_header(&mut data.as_slice()).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedVersion(_)));
+    }
+
+    #[test]
+    fn test_missing_level_dat() {
+        // an archive without the file and an empty one
+        for file in [write_zip(&[("save/control.lua", &[])]), write_zip(&[])] {
+            let err = get_save_header_from_stream(file.get_ref().as_slice()).unwrap_err();
+            assert!(matches!(err, Error::MissingLevelDat));
+            let err = get_save_header_by_path(file).unwrap_err();
+            assert!(matches!(err, Error::MissingLevelDat));
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async() {
+        use futures_lite::{future::block_on, io::Cursor, AsyncReadExt};
+
+        for path in test_saves() {
+            let expected = get_save_header_by_path(File::open(&path).unwrap()).unwrap();
+            let data = std::fs::read(&path).unwrap();
+            let header = block_on(get_save_header_by_path_async(Cursor::new(data))).unwrap();
+            assert_eq!(header, expected, "{path:?}");
+
+            let mut level_data = Vec::new();
+            let mut save = SaveFile::new(File::open(&path).unwrap()).unwrap();
+            save.level_data()
+                .unwrap()
+                .read_to_end(&mut level_data)
+                .unwrap();
+            let header = block_on(get_save_header_async(&mut level_data.as_slice())).unwrap();
+            assert_eq!(header, expected, "{path:?}");
+        }
+
+        let data = [1, 0, 1, 0, 6, 0, 4, 0, 0, 4, b'b', b'a'];
+        let err = block_on(get_save_header_async(&mut data.as_slice())).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedEof));
+
+        let file = write_zip(&[("save/control.lua", &[])]).into_inner();
+        let err = block_on(get_save_header_by_path_async(Cursor::new(file))).unwrap_err();
+        assert!(matches!(err, Error::MissingLevelDat));
+
+        // a campaign name of u32::MAX bytes isn't buffered
+        let data = [1, 0, 1, 0, 6, 0, 4, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        let mut reader = AsyncReadExt::chain(data.as_slice(), futures_lite::io::repeat(0));
+        let err = block_on(get_save_header_async(&mut reader)).unwrap_err();
+        assert!(matches!(err, Error::TooLarge(_)));
+    }
+
+    #[test]
+    fn test_stream() {
+        for path in test_saves() {
+            let header = get_save_header_from_stream(File::open(&path).unwrap()).unwrap();
+            assert_eq!(
+                header,
+                get_save_header_by_path(File::open(&path).unwrap()).unwrap(),
+                "{path:?}"
+            );
+        }
+    }
 }
@@ -0,0 +1,572 @@
+// `script.dat` and `script-init.dat` hold the Lua `global` table (`storage`
+// since 2.0) of the scenario and of every mod, as it was when saving and when
+// the game was started. Up to 0.18 each table is stored as Lua source code
+// that rebuilds it, since 1.0 in a binary format.
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use crate::{
+    error::Error,
+    lua_source,
+    reader::{
+        read_bytes, read_quality_version, read_string, with_capacity, CountingReader,
+        FactorioReader,
+    },
+    saves::FactorioVersion,
+    writer::{
+        write_bytes, write_quality_version, write_string, FactorioOptimizedWriter, FactorioWriter,
+    },
+    Result,
+};
+
+/// A value stored in a Lua table.
+///
+/// Tables keep their entries in the order they were read. A table referenced
+/// more than once is only stored the first time, later references (including
+/// cyclic ones) are [`LuaValue::TableRef`]s to its ID.
+#[derive(PartialEq, Debug, Clone)]
+pub enum LuaValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Table {
+        /// The tables of a storage are numbered from 0 in the order they are
+        /// stored. Written tables are renumbered and their references with
+        /// them, so new tables can use any ID not used by another table.
+        id: u32,
+        entries: Vec<(LuaValue, LuaValue)>,
+    },
+    /// Another reference to the table with this ID. In binary storages the
+    /// table is stored before, in Lua source it can be anywhere in the tree.
+    TableRef(u32),
+    /// A reference to a game object like a `LuaEntity`. In 1.1 these were
+    /// still stored as tables.
+    Object {
+        kind: u32,
+        id: u32,
+    },
+    /// The bytecode of a function, which can only be stored before 1.0.
+    Function(Vec<u8>),
+}
+
+impl LuaValue {
+    /// Looks up the string `key` if this is a table.
+    pub fn get(&self, key: &str) -> Option<&LuaValue> {
+        match self {
+            LuaValue::Table { entries, .. } => entries.iter().find_map(|(k, v)| match k {
+                LuaValue::String(k) if k == key => Some(v),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut LuaValue> {
+        match self {
+            LuaValue::Table { entries, .. } => entries.iter_mut().find_map(|(k, v)| match k {
+                LuaValue::String(k) if k == key => Some(v),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ScriptStorage {
+    /// Lua source code returning the table, used up to 0.18. Strings in it are
+    /// not necessarily valid UTF-8.
+    Source(Vec<u8>),
+    /// The binary format used since 1.0, every table is stored with the
+    /// version that wrote it.
+    Value {
+        factorio_version: FactorioVersion,
+        quality_version: Option<u8>, // since 0.17
+        value: LuaValue,
+    },
+}
+
+impl ScriptStorage {
+    /// The stored table, parsed if it is Lua source.
+    ///
+    /// Fails with [`Error::Unsupported`] for source which uses tables or
+    /// functions as keys.
+    pub fn value(&self) -> Result<Cow<'_, LuaValue>> {
+        match self {
+            ScriptStorage::Source(source) => Ok(Cow::Owned(lua_source::parse(source)?)),
+            ScriptStorage::Value { value, .. } => Ok(Cow::Borrowed(value)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ScriptEntry {
+    /// `level` for the scenario, `mod-<name>` for mods.
+    pub name: String,
+    pub storage: ScriptStorage,
+    pub flag: Option<u8>, // since 0.16, always 1 in the saves we have
+}
+
+/// The contents of a `script.dat` or `script-init.dat` file.
+///
+/// The storage of every entry has to be in the format used by
+/// `factorio_version` for it to be written correctly: [`ScriptStorage::Source`]
+/// before 1.0, [`ScriptStorage::Value`] since. Values are written in the
+/// version stored with them.
+///
+/// # Examples
+///
+/// ```
+/// use factorio::save_file::SaveFile;
+///
+/// let file = std::fs::File::open("test/test_1_1.zip").unwrap();
+/// let script_data = SaveFile::new(file).unwrap().script_data().unwrap().unwrap();
+/// for (name, storage) in script_data.mods() {
+///     println!("{name}: {storage:?}");
+/// }
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct ScriptData {
+    pub factorio_version: FactorioVersion,
+    pub quality_version: Option<u8>, // since 0.17
+    pub entries: Vec<ScriptEntry>,
+}
+
+impl ScriptData {
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        let reader = &mut CountingReader::new(reader);
+        let factorio_version = FactorioVersion::read(&[0, 0, 0, 0].into(), reader)?;
+        let version = &factorio_version;
+        let quality_version = read_quality_version(version, reader)?;
+
+        let count = u32::read(version, reader)?;
+        let mut entries = with_capacity(count);
+        for _ in 0..count {
+            let name = read_string("script data name", version, reader, false)?;
+            let storage = if version >= &[1, 0, 0, 0].into() {
+                let length = u32::read_optimized(version, reader)? as u64;
+                let start = reader.position();
+                let storage = read_storage(reader)?;
+                if reader.position() - start != length {
+                    return Err(Error::Unsupported {
+                        field: "script data",
+                        offset: start,
+                    });
+                }
+                storage
+            } else {
+                ScriptStorage::Source(read_bytes(version, reader, false)?)
+            };
+
+            let flag = if version >= &[0, 16, 0, 0].into() {
+                Some(u8::read(version, reader)?)
+            } else {
+                None
+            };
+            entries.push(ScriptEntry {
+                name,
+                storage,
+                flag,
+            });
+        }
+
+        Ok(Self {
+            factorio_version,
+            quality_version,
+            entries,
+        })
+    }
+
+    /// Writes the file, the inverse of [`ScriptData::read`].
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        let version = &self.factorio_version;
+        version.write(version, writer)?;
+        write_quality_version(self.quality_version, version, writer)?;
+
+        (self.entries.len() as u32).write(version, writer)?;
+        for entry in &self.entries {
+            write_string(&entry.name, version, writer, false)?;
+            match &entry.storage {
+                ScriptStorage::Source(source) => write_bytes(source, version, writer, false)?,
+                ScriptStorage::Value {
+                    factorio_version,
+                    quality_version,
+                    value,
+                } => {
+                    let mut buf = Vec::new();
+                    factorio_version.write(factorio_version, &mut buf)?;
+                    write_quality_version(*quality_version, factorio_version, &mut buf)?;
+                    write_lua_value(
+                        value,
+                        factorio_version,
+                        &mut buf,
+                        &mut TableIds::default(),
+                        0,
+                    )?;
+                    write_bytes(&buf, version, writer, false)?;
+                }
+            }
+
+            if version >= &[0, 16, 0, 0].into() {
+                entry.flag.unwrap_or(1).write(version, writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ScriptStorage> {
+        self.entries.iter().find_map(|entry| {
+            if entry.name == name {
+                Some(&entry.storage)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut ScriptStorage> {
+        self.entries.iter_mut().find_map(|entry| {
+            if entry.name == name {
+                Some(&mut entry.storage)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Removes the table of mod `name`, returns it if there was one.
+    pub fn remove_mod(&mut self, name: &str) -> Option<ScriptStorage> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.name.strip_prefix("mod-") == Some(name))?;
+        Some(self.entries.remove(index).storage)
+    }
+
+    /// The scenario's table.
+    pub fn level(&self) -> Option<&ScriptStorage> {
+        self.get("level")
+    }
+
+    /// The tables of all mods by mod name.
+    pub fn mods(&self) -> impl Iterator<Item = (&str, &ScriptStorage)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| Some((entry.name.strip_prefix("mod-")?, &entry.storage)))
+    }
+}
+
+/// How deep Lua tables may nest, deeper ones would overflow the stack when
+/// read recursively.
+pub(crate) const MAX_LUA_DEPTH: usize = 512;
+
+/// Every binary table starts with the version that wrote it.
+fn read_storage<R: Read>(reader: &mut CountingReader<R>) -> Result<ScriptStorage> {
+    let factorio_version = FactorioVersion::read(&[0, 0, 0, 0].into(), reader)?;
+    let quality_version = read_quality_version(&factorio_version, reader)?;
+    let value = read_lua_value(&factorio_version, reader, &mut 0, 0)?;
+    Ok(ScriptStorage::Value {
+        factorio_version,
+        quality_version,
+        value,
+    })
+}
+
+/// `next_table_id` is the ID of the next table read, `depth` the number of
+/// tables the value is in.
+fn read_lua_value<R: Read>(
+    version: &FactorioVersion,
+    reader: &mut CountingReader<R>,
+    next_table_id: &mut u32,
+    depth: usize,
+) -> Result<LuaValue> {
+    if depth > MAX_LUA_DEPTH {
+        return Err(Error::TooDeep("lua value"));
+    }
+
+    let offset = reader.position();
+    let kind = u8::read(version, reader)?;
+    Ok(match kind {
+        0 => LuaValue::Nil,
+        1 => LuaValue::Bool(true),
+        2 => LuaValue::Bool(false),
+        3 => LuaValue::Number(f64::read(version, reader)?),
+        4 => LuaValue::String(read_string("lua string", version, reader, true)?),
+        5 => {
+            let id = *next_table_id;
+            *next_table_id += 1;
+            let length = u32::read_optimized(version, reader)?;
+            let mut entries = with_capacity(length);
+            for _ in 0..length {
+                let key = read_lua_value(version, reader, next_table_id, depth + 1)?;
+                let value = read_lua_value(version, reader, next_table_id, depth + 1)?;
+                entries.push((key, value));
+            }
+            LuaValue::Table { id, entries }
+        }
+        6 => {
+            let id = u32::read(version, reader)?;
+            if id >= *next_table_id {
+                return Err(Error::InvalidTableRef(id));
+            }
+            LuaValue::TableRef(id)
+        }
+        7 => LuaValue::Object {
+            kind: u32::read(version, reader)?,
+            id: u32::read(version, reader)?,
+        },
+        _ => {
+            return Err(Error::InvalidEnum {
+                field: "lua value type",
+                value: kind,
+                offset,
+            })
+        }
+    })
+}
+
+/// The IDs tables are written with by the ID they have in [`LuaValue::Table`].
+#[derive(Default)]
+struct TableIds(BTreeMap<u32, u32>);
+
+fn write_lua_value(
+    value: &LuaValue,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+    table_ids: &mut TableIds,
+    depth: usize,
+) -> Result<()> {
+    if depth > MAX_LUA_DEPTH {
+        return Err(Error::TooDeep("lua value"));
+    }
+
+    match value {
+        LuaValue::Nil => 0u8.write(version, writer),
+        LuaValue::Bool(true) => 1u8.write(version, writer),
+        LuaValue::Bool(false) => 2u8.write(version, writer),
+        LuaValue::Number(value) => {
+            3u8.write(version, writer)?;
+            value.write(version, writer)
+        }
+        LuaValue::String(value) => {
+            4u8.write(version, writer)?;
+            write_string(value, version, writer, true)
+        }
+        LuaValue::Table { id, entries } => {
+            let written_id = table_ids.0.len() as u32;
+            table_ids.0.insert(*id, written_id);
+            5u8.write(version, writer)?;
+            (entries.len() as u32).write_optimized(version, writer)?;
+            for (key, value) in entries {
+                write_lua_value(key, version, writer, table_ids, depth + 1)?;
+                write_lua_value(value, version, writer, table_ids, depth + 1)?;
+            }
+            Ok(())
+        }
+        LuaValue::TableRef(id) => {
+            let written_id = table_ids.0.get(id).ok_or(Error::InvalidTableRef(*id))?;
+            6u8.write(version, writer)?;
+            written_id.write(version, writer)
+        }
+        LuaValue::Object { kind, id } => {
+            7u8.write(version, writer)?;
+            kind.write(version, writer)?;
+            id.write(version, writer)
+        }
+        LuaValue::Function(_) => Err(Error::UnsupportedValue("lua function")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::Path};
+
+    use super::*;
+    use crate::{save_file::SaveFile, test_util::test_saves};
+
+    fn script_data(path: impl AsRef<Path>) -> ScriptData {
+        SaveFile::new(File::open(path).unwrap())
+            .unwrap()
+            .script_data()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_source() {
+        let data = script_data("test/test_0_13.zip");
+        assert_eq!(data.quality_version, None);
+        assert_eq!(data.entries.len(), 1);
+        let Some(ScriptStorage::Source(source)) = data.level() else {
+            panic!("expected lua source");
+        };
+        assert!(source.starts_with(b"do local _={story={"));
+        let level = data.level().unwrap().value().unwrap();
+        let story = level.get("story").unwrap();
+        assert_eq!(story.get("story_index"), Some(&LuaValue::Number(1.0)));
+
+        // the branches are referenced again after the table
+        let data = script_data("test/test_0_17.zip");
+        let level = data.level().unwrap().value().unwrap();
+        let branches = level
+            .get("story")
+            .and_then(|t| t.get("helpers"))
+            .and_then(|t| t.get("story_branches"))
+            .unwrap();
+        let Some(LuaValue::Table { entries, .. }) = branches
+            .get("helpers")
+            .and_then(|t| t.get("story_branches"))
+        else {
+            panic!("expected a table");
+        };
+        let LuaValue::Table {
+            entries: originals, ..
+        } = branches
+        else {
+            unreachable!();
+        };
+        let Some((_, LuaValue::Table { id, .. })) = originals.first() else {
+            panic!("expected a table");
+        };
+        assert_eq!(entries[0], (LuaValue::Number(1.0), LuaValue::TableRef(*id)));
+
+        let data = script_data("test/test_0_18.zip");
+        assert_eq!(data.quality_version, Some(0));
+        let mods: Vec<_> = data.mods().map(|(name, _)| name).collect();
+        assert_eq!(mods, ["belt-balancer", "train-station-overview"]);
+    }
+
+    #[test]
+    fn test_1_1() {
+        let data = script_data("test/test_1_1.zip");
+        let Some(ScriptStorage::Value { value: level, .. }) = data.level() else {
+            panic!("expected a lua value");
+        };
+        let story = level.get("story").unwrap();
+        assert_eq!(story.get("story_index"), Some(&LuaValue::Number(1.0)));
+        assert!(
+            matches!(story.get("updates"), Some(LuaValue::Table { entries, .. }) if entries.is_empty())
+        );
+        assert_eq!(data.mods().count(), 2);
+    }
+
+    #[test]
+    fn test_2_0() {
+        let data = script_data("test/test_2_0_13_ext.zip");
+        let Some(ScriptStorage::Value { value: level, .. }) = data.level() else {
+            panic!("expected a lua value");
+        };
+        let Some(LuaValue::Table {
+            entries: chests, ..
+        }) = level
+            .get("transport_belt_madness")
+            .and_then(|t| t.get("input_chest"))
+        else {
+            panic!("expected a table");
+        };
+        assert_eq!(
+            chests[0],
+            (LuaValue::Number(1.0), LuaValue::Object { kind: 0, id: 12 })
+        );
+        assert!(data.mods().any(|(name, _)| name == "RateCalculator"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for path in test_saves() {
+            let mut save = SaveFile::new(File::open(path).unwrap()).unwrap();
+            let buf = save.script_dat().unwrap().unwrap();
+            let data = ScriptData::read(&mut buf.as_slice()).unwrap();
+
+            let mut written = Vec::new();
+            data.write(&mut written).unwrap();
+            assert_eq!(written, buf);
+
+            save.script_init_data().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_values() {
+        for path in test_saves() {
+            let data = script_data(path);
+            for entry in &data.entries {
+                entry.storage.value().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_table_reference() {
+        // `{self = <itself>, other = {}}` written by an older version and a
+        // flag of 2
+        let mut storage = vec![1, 0, 1, 0, 5, 0, 0, 0, 0, 5, 2, 4, 4];
+        storage.extend(b"self");
+        storage.extend([6, 0, 0, 0, 0, 4, 5]);
+        storage.extend(b"other");
+        storage.extend([5, 0]);
+        let mut buf = vec![1, 0, 1, 0, 6, 0, 4, 0, 0, 1, 0, 0, 0, 5];
+        buf.extend(b"level");
+        buf.push(storage.len() as u8);
+        buf.extend(&storage);
+        buf.push(2);
+
+        let mut data = ScriptData::read(&mut buf.as_slice()).unwrap();
+        let Some(ScriptStorage::Value { value: level, .. }) = data.level() else {
+            panic!("expected a lua value");
+        };
+        assert_eq!(level.get("self"), Some(&LuaValue::TableRef(0)));
+        assert!(matches!(
+            level.get("other"),
+            Some(LuaValue::Table { id: 1, .. })
+        ));
+        assert_eq!(data.entries[0].flag, Some(2));
+        assert!(matches!(
+            data.level(),
+            Some(ScriptStorage::Value { factorio_version, .. }) if *factorio_version == [1, 1, 5, 0].into()
+        ));
+
+        let mut written = Vec::new();
+        data.write(&mut written).unwrap();
+        assert_eq!(written, buf);
+
+        // a reference to a table that isn't written before it
+        let Some(ScriptStorage::Value { value: level, .. }) = data.get_mut("level") else {
+            unreachable!();
+        };
+        *level.get_mut("self").unwrap() = LuaValue::TableRef(1);
+        let err = data.write(&mut Vec::new()).unwrap_err();
+        assert!(matches!(err, Error::InvalidTableRef(1)));
+    }
+
+    #[test]
+    fn test_too_deep() {
+        // tables holding a single table each, without an end
+        let mut storage = vec![1, 0, 1, 0, 6, 0, 4, 0, 0];
+        storage.extend([5, 1, 0].repeat(100_000));
+        let reader = &mut CountingReader::new(storage.as_slice());
+        let err = read_storage(reader).unwrap_err();
+        assert!(matches!(err, Error::TooDeep("lua value")));
+
+        let mut value = LuaValue::Nil;
+        for id in 0..600 {
+            value = LuaValue::Table {
+                id,
+                entries: vec![(LuaValue::Nil, value)],
+            };
+        }
+        let err = write_lua_value(
+            &value,
+            &[1, 1, 0, 0].into(),
+            &mut Vec::new(),
+            &mut TableIds::default(),
+            0,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::TooDeep("lua value")));
+    }
+}
@@ -0,0 +1,305 @@
+// Where the files of a save come from. Saves are zip archives, but scenarios
+// and campaign levels are plain folders with the same files in them.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use zip::{result::ZipError, write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::Result;
+
+/// A file opened from a [`SaveSource`].
+pub struct SourceFile<'a> {
+    reader: Box<dyn Read + 'a>,
+    size: u64,
+}
+
+impl<'a> SourceFile<'a> {
+    pub fn new(reader: impl Read + 'a, size: u64) -> Self {
+        Self {
+            reader: Box::new(reader),
+            size,
+        }
+    }
+
+    /// The size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for SourceFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// A collection of files making up a save.
+///
+/// Names use `/` as separator on every platform.
+pub trait SaveSource {
+    /// The names of all files, folders may be included with a trailing `/`.
+    fn file_names(&self) -> Box<dyn Iterator<Item = &str> + '_>;
+
+    /// Opens the file `name`, returns `None` if it does not exist.
+    fn open(&mut self, name: &str) -> Result<Option<SourceFile<'_>>>;
+
+    fn contains(&self, name: &str) -> bool {
+        self.file_names().any(|file_name| file_name == name)
+    }
+
+    /// Adds the file `name` to `zip`, with `data` as its contents if given.
+    /// Used to write modified copies of a save, files are deflated unless the
+    /// source knows better.
+    fn write_to_zip<W: Write + Seek>(
+        &mut self,
+        name: &str,
+        data: Option<&[u8]>,
+        zip: &mut ZipWriter<W>,
+    ) -> Result<()>
+    where
+        Self: Sized,
+    {
+        zip.start_file(name, SimpleFileOptions::default())?;
+        match data {
+            Some(data) => zip.write_all(data)?,
+            None => {
+                let mut file = self.open(name)?.ok_or(ZipError::FileNotFound)?;
+                io::copy(&mut file, zip)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> SaveSource for ZipArchive<R> {
+    fn file_names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(ZipArchive::file_names(self))
+    }
+
+    fn open(&mut self, name: &str) -> Result<Option<SourceFile<'_>>> {
+        match self.by_name(name) {
+            Ok(file) => {
+                let size = file.size();
+                Ok(Some(SourceFile::new(file, size)))
+            }
+            Err(ZipError::FileNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.index_for_name(name).is_some()
+    }
+
+    /// Unchanged entries are copied without recompressing them, changed ones
+    /// keep their compression method and modification time.
+    fn write_to_zip<W: Write + Seek>(
+        &mut self,
+        name: &str,
+        data: Option<&[u8]>,
+        zip: &mut ZipWriter<W>,
+    ) -> Result<()> {
+        let index = self.index_for_name(name).ok_or(ZipError::FileNotFound)?;
+        let file = self.by_index_raw(index)?;
+        let Some(data) = data else {
+            zip.raw_copy_file(file)?;
+            return Ok(());
+        };
+
+        let mut options = SimpleFileOptions::default().compression_method(file.compression());
+        if let Some(last_modified) = file.last_modified() {
+            options = options.last_modified_time(last_modified);
+        }
+        zip.start_file(name, options)?;
+        zip.write_all(data)?;
+        Ok(())
+    }
+}
+
+/// An unpacked save or scenario folder on disk.
+///
+/// # Examples
+///
+/// ```no_run
+/// use factorio::{save_file::SaveFile, source::DirectorySource};
+///
+/// let source = DirectorySource::new("scenarios/my-scenario").unwrap();
+/// let header = SaveFile::from_source(source).unwrap().header().unwrap();
+/// ```
+pub struct DirectorySource {
+    root: PathBuf,
+    files: Vec<String>,
+}
+
+impl DirectorySource {
+    /// Lists all files below `root`, later changes to the folder are not
+    /// picked up.
+    pub fn new(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let mut files = Vec::new();
+        list_files(&root, "", &mut files)?;
+        files.sort();
+        Ok(Self { root, files })
+    }
+}
+
+fn list_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{name}/"), files)?;
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+impl SaveSource for DirectorySource {
+    fn file_names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.files.iter().map(String::as_str))
+    }
+
+    fn open(&mut self, name: &str) -> Result<Option<SourceFile<'_>>> {
+        if !self.contains(name) {
+            return Ok(None);
+        }
+
+        let file = File::open(self.root.join(name))?;
+        let size = file.metadata()?.len();
+        Ok(Some(SourceFile::new(file, size)))
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.files
+            .binary_search_by(|file| file.as_str().cmp(name))
+            .is_ok()
+    }
+}
+
+/// Files kept in memory, e.g. for tests or saves assembled on the fly.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct MemorySource {
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, data: impl Into<Vec<u8>>) {
+        self.files.insert(name.into(), data.into());
+    }
+}
+
+impl From<BTreeMap<String, Vec<u8>>> for MemorySource {
+    fn from(files: BTreeMap<String, Vec<u8>>) -> Self {
+        Self { files }
+    }
+}
+
+impl SaveSource for MemorySource {
+    fn file_names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.files.keys().map(String::as_str))
+    }
+
+    fn open(&mut self, name: &str) -> Result<Option<SourceFile<'_>>> {
+        Ok(self
+            .files
+            .get(name)
+            .map(|data| SourceFile::new(Cursor::new(data.as_slice()), data.len() as u64)))
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.files.contains_key(name)
+    }
+}
+
+/// Anything a save can be opened from: a reader to a zip archive, a
+/// [`DirectorySource`] or a [`MemorySource`].
+pub trait IntoSaveSource {
+    type Source: SaveSource;
+
+    fn into_save_source(self) -> Result<Self::Source>;
+}
+
+impl<R: Read + Seek> IntoSaveSource for R {
+    type Source = ZipArchive<R>;
+
+    fn into_save_source(self) -> Result<Self::Source> {
+        Ok(ZipArchive::new(self)?)
+    }
+}
+
+impl IntoSaveSource for DirectorySource {
+    type Source = Self;
+
+    fn into_save_source(self) -> Result<Self::Source> {
+        Ok(self)
+    }
+}
+
+impl IntoSaveSource for MemorySource {
+    type Source = Self;
+
+    fn into_save_source(self) -> Result<Self::Source> {
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{save_file::SaveFile, saves::get_save_header_by_path, test_util::memory_source};
+
+    fn open_zip(path: &str) -> ZipArchive<File> {
+        ZipArchive::new(File::open(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_directory() {
+        let dir = std::env::temp_dir().join(format!("factorio-test-{}", std::process::id()));
+        open_zip("test/test_2_0_13_ext.zip").extract(&dir).unwrap();
+
+        let expected =
+            get_save_header_by_path(File::open("test/test_2_0_13_ext.zip").unwrap()).unwrap();
+        let mut save = SaveFile::from_source(DirectorySource::new(&dir).unwrap()).unwrap();
+        assert_eq!(save.root(), "test_2_0_13_ext");
+        assert_eq!(save.header().unwrap(), expected);
+
+        // the save folder itself, as found in `scenarios/`
+        let source = DirectorySource::new(dir.join("test_2_0_13_ext")).unwrap();
+        assert!(source.contains("level.dat0"));
+        assert!(!source.contains("level.dat"));
+        let mut save = SaveFile::from_source(source).unwrap();
+        assert_eq!(save.root(), "");
+        assert_eq!(save.header().unwrap(), expected);
+        assert!(save.control_lua().unwrap().is_some());
+        assert_eq!(save.read_file("missing").unwrap(), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_memory() {
+        let archive = open_zip("test/test_1_1.zip");
+        let source = memory_source("test/test_1_1.zip");
+
+        let mut save = SaveFile::from_source(source.clone()).unwrap();
+        let mut zip_save = SaveFile::from_source(archive).unwrap();
+        let mut files: Vec<_> = zip_save.files().collect();
+        files.sort();
+        assert!(save.files().eq(files));
+        assert_eq!(save.preview().unwrap(), zip_save.preview().unwrap());
+        assert_eq!(
+            get_save_header_by_path(source).unwrap(),
+            zip_save.header().unwrap()
+        );
+    }
+}
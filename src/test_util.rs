@@ -0,0 +1,46 @@
+// Fixtures shared by the tests of several modules.
+use std::{
+    fs::File,
+    io::{Cursor, Read, Write},
+    path::PathBuf,
+};
+
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::source::MemorySource;
+
+/// The paths of all saves in the `test` folder.
+pub(crate) fn test_saves() -> Vec<PathBuf> {
+    let mut paths: Vec<_> = std::fs::read_dir("test")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "zip"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// A zip archive holding `entries`, deflated.
+pub(crate) fn write_zip(entries: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in entries {
+        zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    let mut file = zip.finish().unwrap();
+    file.set_position(0);
+    file
+}
+
+/// The files of the save at `path`, unpacked into memory.
+pub(crate) fn memory_source(path: &str) -> MemorySource {
+    let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+    let mut source = MemorySource::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).unwrap();
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).unwrap();
+        source.insert(file.name(), buf);
+    }
+    source
+}
@@ -0,0 +1,271 @@
+// Counterpart to `reader.rs`, every `write_*` function produces exactly the
+// bytes its `read_*` sibling consumes.
+use std::io::Write;
+
+use crate::{
+    error::Error,
+    property_tree::{PropertyTree, PropertyTreeValue},
+    reader::MAX_PROPERTY_TREE_DEPTH,
+    saves::FactorioVersion,
+    Result,
+};
+
+pub(crate) trait FactorioWriter {
+    fn write(&self, version: &FactorioVersion, writer: &mut impl Write) -> Result<()>;
+}
+
+/// The optimized encoding only fits unsigned integers, signed and floating
+/// point values are always written whole.
+pub(crate) trait FactorioOptimizedWriter: FactorioWriter {
+    fn write_optimized(&self, version: &FactorioVersion, writer: &mut impl Write) -> Result<()>;
+}
+
+macro_rules! write_num_impl {
+    ($int:ty) => {
+        impl FactorioWriter for $int {
+            fn write(&self, _version: &FactorioVersion, writer: &mut impl Write) -> Result<()> {
+                writer.write_all(&self.to_le_bytes())?;
+                Ok(())
+            }
+        }
+    };
+    ($($int:ty),*) => {$(write_num_impl!($int);)*}
+}
+
+write_num_impl!(u8, u16, u32, u64, i64, f64);
+
+macro_rules! write_optimized_impl {
+    ($int:ty) => {
+        impl FactorioOptimizedWriter for $int {
+            fn write_optimized(&self, version: &FactorioVersion, writer: &mut impl Write) -> Result<()> {
+                if *self < u8::MAX as $int {
+                    return (*self as u8).write(version, writer);
+                }
+
+                // otherwise the marker is followed by the whole value
+                u8::MAX.write(version, writer)?;
+                self.write(version, writer)
+            }
+        }
+    };
+    ($($int:ty),*) => {$(write_optimized_impl!($int);)*}
+}
+
+write_optimized_impl!(u8, u16, u32, u64);
+
+pub(crate) fn write_string(
+    value: &str,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+    force_optimized: bool,
+) -> Result<()> {
+    write_bytes(value.as_bytes(), version, writer, force_optimized)
+}
+
+pub(crate) fn write_bytes(
+    value: &[u8],
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+    force_optimized: bool,
+) -> Result<()> {
+    let length = value.len() as u32;
+    if version >= &[0, 16, 0, 0].into() || force_optimized {
+        length.write_optimized(version, writer)?;
+    } else {
+        length.write(version, writer)?;
+    }
+
+    writer.write_all(value)?;
+    Ok(())
+}
+
+/// Unwraps an optional field which exists in `version`, a missing value is an
+/// error rather than being written as zero.
+pub(crate) fn required<T>(
+    field: &'static str,
+    value: Option<T>,
+    version: &FactorioVersion,
+) -> Result<T> {
+    value.ok_or(Error::MissingField {
+        field,
+        version: *version,
+    })
+}
+
+pub(crate) fn write_quality_version(
+    value: Option<u8>,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    if version >= &[0, 17, 0, 0].into() {
+        required("quality version", value, version)?.write(version, writer)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_allow_non_admin_debug_options(
+    value: Option<bool>,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    if version >= &[0, 16, 0, 0].into() {
+        (required("allow non admin debug options", value, version)? as u8)
+            .write(version, writer)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_loaded_from(
+    value: &[u16; 3],
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    for part in value {
+        if version >= &[0, 14, 14, 0].into() {
+            part.write_optimized(version, writer)?;
+        } else {
+            part.write(version, writer)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn write_array<T: FactorioWriter>(
+    values: &[T],
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let length = values.len() as u32;
+    if version >= &[0, 16, 0, 0].into() {
+        length.write_optimized(version, writer)?;
+    } else {
+        length.write(version, writer)?;
+    }
+
+    for value in values {
+        value.write(version, writer)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_build_version(
+    value: u32,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    if version >= &[2, 0, 0, 0].into() {
+        value.write(version, writer)
+    } else {
+        (value as u16).write(version, writer)
+    }
+}
+
+pub(crate) fn write_large_blueprint_size(
+    value: Option<u32>,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    if version >= &[2, 0, 0, 0].into() {
+        required("large blueprint size", value, version)?.write(version, writer)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_mod_settings_crc(
+    value: Option<u32>,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    if version >= &[0, 16, 0, 0].into() {
+        required("mod settings crc", value, version)?.write(version, writer)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_startup_mod_settings(
+    value: Option<&PropertyTree>,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    if version >= &[0, 16, 0, 0].into() {
+        write_property_tree(
+            required("startup mod settings", value, version)?,
+            version,
+            writer,
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_property_tree(
+    value: &PropertyTree,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    write_property_tree_node(value, version, writer, 0)
+}
+
+fn write_property_tree_node(
+    node: &PropertyTree,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+    depth: usize,
+) -> Result<()> {
+    // the reader would refuse the tree anyway
+    if depth > MAX_PROPERTY_TREE_DEPTH {
+        return Err(Error::TooDeep("property tree"));
+    }
+
+    let kind: u8 = match node.value {
+        PropertyTreeValue::None => 0,
+        PropertyTreeValue::Bool(_) => 1,
+        PropertyTreeValue::Number(_) => 2,
+        PropertyTreeValue::String(_) => 3,
+        PropertyTreeValue::List(_) => 4,
+        PropertyTreeValue::Dictionary(_) => 5,
+        PropertyTreeValue::SignedInteger(_) => 6,
+        PropertyTreeValue::UnsignedInteger(_) => 7,
+    };
+    kind.write(version, writer)?;
+    if version >= &[0, 17, 0, 0].into() {
+        (node.any_type as u8).write(version, writer)?;
+    }
+
+    match &node.value {
+        PropertyTreeValue::None => Ok(()),
+        PropertyTreeValue::Bool(value) => (*value as u8).write(version, writer),
+        PropertyTreeValue::Number(value) => value.write(version, writer),
+        PropertyTreeValue::String(value) => write_property_tree_string(value, version, writer),
+        PropertyTreeValue::List(values) => {
+            (values.len() as u32).write(version, writer)?;
+            for value in values {
+                write_property_tree_string("", version, writer)?;
+                write_property_tree_node(value, version, writer, depth + 1)?;
+            }
+            Ok(())
+        }
+        PropertyTreeValue::Dictionary(entries) => {
+            (entries.len() as u32).write(version, writer)?;
+            for (key, value) in entries {
+                write_property_tree_string(key, version, writer)?;
+                write_property_tree_node(value, version, writer, depth + 1)?;
+            }
+            Ok(())
+        }
+        PropertyTreeValue::SignedInteger(value) => value.write(version, writer),
+        PropertyTreeValue::UnsignedInteger(value) => value.write(version, writer),
+    }
+}
+
+fn write_property_tree_string(
+    value: &str,
+    version: &FactorioVersion,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let empty = value.is_empty();
+    (empty as u8).write(version, writer)?;
+    if empty {
+        return Ok(());
+    }
+    write_string(value, version, writer, true)
+}
@@ -0,0 +1,238 @@
+// Walks the local file headers of a zip archive front to back, for streams that
+// can't seek to the central directory at the end. Entries are skipped using the
+// sizes in their local header or, if those are only stored in a data
+// descriptor after the data (as in 2.0 saves), by decompressing them.
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, Read},
+};
+
+use flate2::bufread::DeflateDecoder;
+
+use crate::{error::Error, reader::FactorioReader, saves::FactorioVersion, Result};
+
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+pub(crate) struct LocalEntry {
+    pub(crate) name: String,
+    method: u16,
+    zip64: bool,
+    /// `None` if the sizes follow the data in a data descriptor.
+    compressed_size: Option<u64>,
+}
+
+pub(crate) struct ZipStream<R> {
+    reader: R,
+}
+
+impl<R: BufRead> ZipStream<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Reads the next local file header, returns `None` once the central
+    /// directory is reached. The data of the previous entry has to be skipped
+    /// with [`ZipStream::skip`] first.
+    pub(crate) fn next_entry(&mut self) -> Result<Option<LocalEntry>> {
+        let version = &[0, 0, 0, 0].into();
+        let reader = &mut self.reader;
+        match u32::read(version, reader)? {
+            LOCAL_FILE_HEADER => {}
+            // an empty archive has no central directory headers
+            CENTRAL_DIRECTORY_HEADER | END_OF_CENTRAL_DIRECTORY => return Ok(None),
+            _ => return Err(Error::InvalidZip("invalid local file header")),
+        }
+
+        let version_needed = u16::read(version, reader)?;
+        let flags = u16::read(version, reader)?;
+        let method = u16::read(version, reader)?;
+        u32::read(version, reader)?; // modification time and date
+        u32::read(version, reader)?; // crc32
+        let mut compressed_size = u32::read(version, reader)? as u64;
+        u32::read(version, reader)?; // uncompressed size
+        let name_length = u16::read(version, reader)?;
+        let extra_length = u16::read(version, reader)?;
+
+        let mut name = vec![0; name_length as _];
+        reader.read_exact(&mut name)?;
+        let mut extra = vec![0; extra_length as _];
+        reader.read_exact(&mut extra)?;
+
+        if flags & 1 != 0 {
+            return Err(Error::UnsupportedZip("encrypted entry"));
+        }
+
+        let zip64_extra = read_zip64_extra(&extra)?;
+        if let Some(size) = zip64_extra {
+            compressed_size = size;
+        }
+
+        Ok(Some(LocalEntry {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            method,
+            // 2.0 saves use 64 bit data descriptors without a zip64 extra field
+            zip64: zip64_extra.is_some() || version_needed >= 45,
+            compressed_size: if flags & (1 << 3) != 0 {
+                None
+            } else {
+                Some(compressed_size)
+            },
+        }))
+    }
+
+    /// The decompressed data of `entry`, the stream can't be used for further
+    /// entries afterwards. Stored data followed by a data descriptor runs on
+    /// into the rest of the archive, so only read as much as the file's own
+    /// format needs.
+    pub(crate) fn contents(&mut self, entry: &LocalEntry) -> Result<Box<dyn Read + '_>> {
+        Ok(match (entry.method, entry.compressed_size) {
+            (STORED, Some(size)) => Box::new((&mut self.reader).take(size)),
+            (STORED, None) => Box::new(&mut self.reader),
+            (DEFLATED, Some(size)) => Box::new(DeflateDecoder::new((&mut self.reader).take(size))),
+            (DEFLATED, None) => Box::new(DeflateDecoder::new(&mut self.reader)),
+            _ => return Err(Error::UnsupportedZip("compression method")),
+        })
+    }
+
+    /// Skips over the data of `entry` and its data descriptor.
+    pub(crate) fn skip(&mut self, entry: &LocalEntry) -> Result<()> {
+        if let Some(size) = entry.compressed_size {
+            let skipped = io::copy(&mut (&mut self.reader).take(size), &mut io::sink())?;
+            if skipped != size {
+                return Err(Error::UnexpectedEof);
+            }
+            return Ok(());
+        }
+
+        if entry.method == STORED {
+            return self.skip_stored(entry);
+        }
+        if entry.method != DEFLATED {
+            return Err(Error::UnsupportedZip("compression method"));
+        }
+
+        // the compressed data has to be decompressed to find where it ends
+        let mut decoder = DeflateDecoder::new(&mut self.reader);
+        let uncompressed_size = io::copy(&mut decoder, &mut io::sink())?;
+        let compressed_size = decoder.total_in();
+
+        let version = &[0, 0, 0, 0].into();
+        let reader = &mut self.reader;
+        // the signature is optional
+        if u32::read(version, reader)? == DATA_DESCRIPTOR {
+            u32::read(version, reader)?; // crc32
+        }
+        let sizes = if entry.zip64 {
+            (u64::read(version, reader)?, u64::read(version, reader)?)
+        } else {
+            (
+                u32::read(version, reader)? as u64,
+                u32::read(version, reader)? as u64,
+            )
+        };
+        if sizes != (compressed_size, uncompressed_size) {
+            return Err(Error::InvalidZip("data descriptor does not match the data"));
+        }
+        Ok(())
+    }
+
+    /// Stored data doesn't show where it ends, so this looks for a data
+    /// descriptor (with signature) holding the number of bytes read so far.
+    /// 2.0 saves store `level.dat1` this way, in front of `level.dat0`.
+    fn skip_stored(&mut self, entry: &LocalEntry) -> Result<()> {
+        let descriptor_length = if entry.zip64 { 24 } else { 16 };
+        let mut window = VecDeque::with_capacity(descriptor_length);
+        let mut position = 0u64;
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Err(Error::UnexpectedEof);
+            }
+
+            let mut found = None;
+            for (i, &byte) in buf.iter().enumerate() {
+                if window.len() == descriptor_length {
+                    window.pop_front();
+                }
+                window.push_back(byte);
+                position += 1;
+
+                if window.len() == descriptor_length
+                    && window.iter().take(4).eq(&DATA_DESCRIPTOR.to_le_bytes())
+                    && is_descriptor_of(
+                        window.make_contiguous(),
+                        position - descriptor_length as u64,
+                    )
+                {
+                    found = Some(i + 1);
+                    break;
+                }
+            }
+
+            match found {
+                Some(consumed) => {
+                    self.reader.consume(consumed);
+                    return Ok(());
+                }
+                None => {
+                    let consumed = buf.len();
+                    self.reader.consume(consumed);
+                }
+            }
+        }
+    }
+}
+
+/// Whether `descriptor` holds `size` as both the compressed and uncompressed
+/// size.
+fn is_descriptor_of(descriptor: &[u8], size: u64) -> bool {
+    let version: &FactorioVersion = &[0, 0, 0, 0].into();
+    let mut sizes = &descriptor[8..];
+    let (compressed, uncompressed) = if descriptor.len() == 24 {
+        (
+            u64::read(version, &mut sizes),
+            u64::read(version, &mut sizes),
+        )
+    } else {
+        (
+            u32::read(version, &mut sizes).map(u64::from),
+            u32::read(version, &mut sizes).map(u64::from),
+        )
+    };
+    matches!((compressed, uncompressed), (Ok(c), Ok(u)) if c == size && u == size)
+}
+
+/// The data of the zip64 extra field in `extra`, if there is one.
+fn read_zip64_extra_field(mut extra: &[u8]) -> Option<&[u8]> {
+    while extra.len() >= 4 {
+        let id = u16::from_le_bytes([extra[0], extra[1]]);
+        let length = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let data = extra.get(4..4 + length)?;
+        if id == ZIP64_EXTRA_FIELD {
+            return Some(data);
+        }
+        extra = &extra[4 + length..];
+    }
+    None
+}
+
+/// Returns the compressed size from the zip64 extra field of a local header,
+/// if there is one.
+fn read_zip64_extra(extra: &[u8]) -> Result<Option<u64>> {
+    // the local header always holds both sizes, uncompressed first
+    match read_zip64_extra_field(extra) {
+        Some(mut data) if data.len() >= 16 => {
+            let version = &[0, 0, 0, 0].into();
+            u64::read(version, &mut data)?;
+            Ok(Some(u64::read(version, &mut data)?))
+        }
+        _ => Ok(None),
+    }
+}